@@ -1,33 +1,81 @@
 extern crate bitflags;
 extern crate chrono;
 extern crate serde;
+extern crate serde_json;
 
 use self::bitflags::bitflags;
-use self::chrono::{DateTime,  Weekday, Duration, Local, Timelike, Datelike};
+use self::chrono::{DateTime,  Weekday, Duration, Local, Datelike, NaiveDate, TimeZone, LocalResult};
 
 use self::serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+use fade::FadeCurve;
+use validation::{bounded_seconds, check, ValidationError};
+
+/// Longest fade, snooze or auto stop time an alarm may have.
+pub const MAX_LENGTH_S: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Time {
     hour: u8,
     min:  u8,
 }
 
 impl Time {
-    pub fn new(hour: u8, min: u8) -> Time {
-        Time { hour, min }
-    }
-    pub fn from_str(s: &str) -> Time {
-        let mut time_iter = s.split(':');
-        let hour = time_iter.next().unwrap().parse::<u8>().unwrap();
-        let min  = time_iter.next().unwrap().parse::<u8>().unwrap();
-        Time { hour, min }
+    /// Fails unless `hour` and `min` make up a time of day.
+    pub fn new(hour: u8, min: u8) -> Result<Time, ValidationError> {
+        check(hour < 24, "hour", hour, "0 to 23")?;
+        check(min < 60, "minute", min, "0 to 59")?;
+        Ok(Time { hour, min })
     }
     pub fn to_str(&self) -> String {
         format!("{:02}:{:02}", self.hour, self.min)
     }
 }
 
+/// Parses a time in the `HH:MM` format used by HTML time inputs.
+impl<'a> TryFrom<&'a str> for Time {
+    type Error = ValidationError;
+
+    fn try_from(s: &'a str) -> Result<Time, ValidationError> {
+        let mut parts = s.split(':').map(|p| p.trim().parse::<u8>().ok());
+        match (parts.next(), parts.next()) {
+            (Some(Some(hour)), Some(Some(min))) => Time::new(hour, min),
+            _ => Err(ValidationError::Syntax(format!("'{}' is not a time of day, expected HH:MM", s))),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Date {
+    year:  i32,
+    month: u8,
+    day:   u8,
+}
+
+impl Date {
+    pub fn from_naive(date: NaiveDate) -> Date {
+        Date { year: date.year(), month: date.month() as u8, day: date.day() as u8 }
+    }
+    pub fn to_str(self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+    pub fn to_naive(self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year, self.month as u32, self.day as u32)
+    }
+}
+
+/// Parses a date in the `YYYY-MM-DD` format used by HTML date inputs.
+impl<'a> TryFrom<&'a str> for Date {
+    type Error = ValidationError;
+
+    fn try_from(s: &'a str) -> Result<Date, ValidationError> {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .map(Date::from_naive)
+            .map_err(|e| ValidationError::Syntax(format!("'{}' is not a date, expected YYYY-MM-DD ({})", s, e)))
+    }
+}
+
 bitflags! {
     #[derive(Deserialize, Serialize)]
     pub struct DayMask: u8 {
@@ -59,31 +107,113 @@ impl DayMask {
     }
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub enum AlarmMode {
     OneTime,
     Recurring(DayMask),
+    Dated(Date),
+}
+
+/// What to play when an alarm goes off.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub enum AlarmSource {
+    #[default]
+    ResumeQueue,
+    Playlist(String),
+    Directory(String),
+    Stream(String),
 }
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+// Settings missing from the config file take their default values
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Alarm {
-    enabled:   bool,
-    time:      Time,
-    length_s:  i64,
-    start_vol: f32,
-    end_vol:   f32,
-    mode:      AlarmMode,
+    name:             String,
+    enabled:          bool,
+    time:             Time,
+    length_s:         i64,
+    start_vol:        f32,
+    end_vol:          f32,
+    mode:             AlarmMode,
+    fade_curve:       FadeCurve,
+    source:           AlarmSource,
+    // `None` leaves the current mpd setting unchanged
+    shuffle:          Option<bool>,
+    repeat:           Option<bool>,
+    snooze_length_s:  i64,
+    max_snoozes:      u32,
+    auto_stop_s:      Option<i64>,
+    auto_stop_fade_s: i64,
+    skip_next:        bool,
+    // Unix timestamp of the last occurrence that went off (or was skipped)
+    last_fired:       Option<i64>,
+}
+
+fn default_snooze_length_s() -> i64 {
+    9 * 60
+}
+
+fn default_max_snoozes() -> u32 {
+    3
+}
+
+fn default_auto_stop_fade_s() -> i64 {
+    30
 }
 
 impl Default for Alarm {
     fn default() -> Self {
-        Self {enabled: true, time: Time::new(6,45), length_s: 10, start_vol: 0.1, end_vol: 0.7, mode: AlarmMode::Recurring(DayMask::default()) }
+        Self {name: "Alarm".to_string(), enabled: true, time: Time { hour: 6, min: 45 }, length_s: 10, start_vol: 0.1, end_vol: 0.7, mode: AlarmMode::Recurring(DayMask::default()),
+              fade_curve: FadeCurve::default(), source: AlarmSource::default(), shuffle: None, repeat: None,
+              snooze_length_s: default_snooze_length_s(), max_snoozes: default_max_snoozes(),
+              auto_stop_s: None, auto_stop_fade_s: default_auto_stop_fade_s(), skip_next: false,
+              last_fired: None }
+    }
+}
+
+/// Reads an alarm from the config file, checking its settings.
+impl TryFrom<serde_json::Value> for Alarm {
+    type Error = ValidationError;
+
+    fn try_from(value: serde_json::Value) -> Result<Alarm, ValidationError> {
+        let alarm: Alarm = serde_json::from_value(value)?;
+        alarm.check()?;
+        Ok(alarm)
     }
 }
 
 impl Alarm {
-    pub fn new(enabled: bool, time: Time, length_s: i64, start_vol: f32, end_vol: f32, mode: AlarmMode) -> Alarm {
-        Alarm { enabled, time, length_s, start_vol, end_vol, mode }
+    pub fn new(name: String, enabled: bool, time: Time, length_s: i64, start_vol: f32, end_vol: f32, mode: AlarmMode) -> Alarm {
+        Alarm { name, enabled, time, length_s, start_vol, end_vol, mode, fade_curve: FadeCurve::default(),
+                source: AlarmSource::default(), shuffle: None, repeat: None,
+                snooze_length_s: default_snooze_length_s(), max_snoozes: default_max_snoozes(),
+                auto_stop_s: None, auto_stop_fade_s: default_auto_stop_fade_s(), skip_next: false,
+                last_fired: None }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks that the settings are within their ranges.
+    pub fn check(&self) -> Result<(), ValidationError> {
+        Time::new(self.time.hour, self.time.min)?;
+        check(self.length_s >= 0 && self.length_s <= MAX_LENGTH_S, "fade length", format!("{} s", self.length_s),
+              "zero to 24 hours")?;
+        for &(field, vol) in &[("start volume", self.start_vol), ("end volume", self.end_vol)] {
+            check((0.0..=1.0).contains(&vol), field, format!("{}%", vol * 100.0), "0% to 100%")?;
+        }
+        if let AlarmMode::Dated(date) = self.mode {
+            check(date.to_naive().is_some(), "date", date.to_str(), "a calendar date")?;
+        }
+        check(self.snooze_length_s >= 0 && self.snooze_length_s <= MAX_LENGTH_S, "snooze length",
+              format!("{} s", self.snooze_length_s), "zero to 24 hours")?;
+        if let Some(auto_stop_s) = self.auto_stop_s {
+            check(auto_stop_s > 0 && auto_stop_s <= MAX_LENGTH_S, "auto stop time", format!("{} s", auto_stop_s),
+                  "more than zero, up to 24 hours")?;
+        }
+        check(self.auto_stop_fade_s >= 0 && self.auto_stop_fade_s <= MAX_LENGTH_S, "auto stop fade length",
+              format!("{} s", self.auto_stop_fade_s), "zero to 24 hours")
     }
 
     pub fn to_str(&self) -> String {
@@ -92,6 +222,13 @@ impl Alarm {
                 AlarmMode::OneTime => {
                     format!("{} (1-time)", self.time.to_str())
                 }
+                AlarmMode::Dated(date) => match date.to_naive() {
+                    Some(d) => format!("{} ({})", self.time.to_str(), d.format("%d %b")),
+                    None => format!("{} (bad date)", self.time.to_str()),
+                },
+                AlarmMode::Recurring(_) if self.skip_next => {
+                    format!("{} (skip)", self.time.to_str())
+                }
                 AlarmMode::Recurring(dm) => {
                     if dm == DayMask::from_bits_truncate(0b0001_1111) {
                         format!("{} (M-F)", self.time.to_str())
@@ -111,7 +248,7 @@ impl Alarm {
     }
 
     pub fn get_length(&self) -> Duration {
-        Duration::seconds(self.length_s)
+        bounded_seconds(self.length_s, MAX_LENGTH_S)
     }
 
     pub fn get_start_vol(&self) -> f32 {
@@ -122,6 +259,59 @@ impl Alarm {
         self.end_vol
     }
 
+    pub fn get_fade_curve(&self) -> FadeCurve {
+        self.fade_curve
+    }
+
+    pub fn set_fade_curve(&mut self, curve: FadeCurve) {
+        self.fade_curve = curve;
+    }
+
+    pub fn get_source(&self) -> &AlarmSource {
+        &self.source
+    }
+
+    pub fn get_shuffle(&self) -> Option<bool> {
+        self.shuffle
+    }
+
+    pub fn get_repeat(&self) -> Option<bool> {
+        self.repeat
+    }
+
+    pub fn set_source(&mut self, source: AlarmSource, shuffle: Option<bool>, repeat: Option<bool>) {
+        self.source = source;
+        self.shuffle = shuffle;
+        self.repeat = repeat;
+    }
+
+    pub fn get_snooze_length(&self) -> Duration {
+        bounded_seconds(self.snooze_length_s, MAX_LENGTH_S)
+    }
+
+    pub fn get_max_snoozes(&self) -> u32 {
+        self.max_snoozes
+    }
+
+    pub fn set_snooze(&mut self, length_s: i64, max_snoozes: u32) {
+        self.snooze_length_s = length_s;
+        self.max_snoozes = max_snoozes;
+    }
+
+    /// How long the alarm plays before it is stopped automatically, if at all.
+    pub fn get_auto_stop(&self) -> Option<Duration> {
+        self.auto_stop_s.map(|s| bounded_seconds(s, MAX_LENGTH_S))
+    }
+
+    pub fn get_auto_stop_fade(&self) -> Duration {
+        bounded_seconds(self.auto_stop_fade_s, MAX_LENGTH_S)
+    }
+
+    pub fn set_auto_stop(&mut self, length_s: Option<i64>, fade_s: i64) {
+        self.auto_stop_s = length_s;
+        self.auto_stop_fade_s = fade_s;
+    }
+
     pub fn get_mode(&self) -> AlarmMode {
         self.mode
     }
@@ -130,32 +320,296 @@ impl Alarm {
         self.enabled
     }
 
-    pub fn toggle_enabled(&mut self) {
-        self.enabled = !self.enabled;
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_skipping_next(&self) -> bool {
+        self.skip_next
+    }
+
+    /// Skip the next occurrence of a recurring alarm. Has no effect on
+    /// one-time alarms.
+    pub fn set_skip_next(&mut self, skip: bool) {
+        self.skip_next = skip && matches!(self.mode, AlarmMode::Recurring(_));
+    }
+
+    /// Step through the states reachable with the alarm button: enabled,
+    /// skipping the next occurrence (recurring alarms only) and disabled.
+    pub fn cycle_enabled(&mut self) {
+        if !self.enabled {
+            self.enabled = true;
+            self.skip_next = false;
+        } else if !self.skip_next && matches!(self.mode, AlarmMode::Recurring(_)) {
+            self.skip_next = true;
+        } else {
+            self.enabled = false;
+            self.skip_next = false;
+        }
     }
 
     pub fn get_time(&self) -> Time {
         self.time
     }
 
-    pub fn should_start(&self, datetime: &DateTime<Local>) -> bool {
-        if !self.enabled
-            || datetime.second() != 0
-            || datetime.hour() != self.time.hour as u32
-            || datetime.minute() != self.time.min as u32
-        {
+    /// The instant the alarm goes off on `date`, if it does so that day.
+    /// A time that falls into a DST gap is moved forward past the gap, and
+    /// an ambiguous time resolves to its first occurrence.
+    fn occurrence_on(&self, date: NaiveDate) -> Option<DateTime<Local>> {
+        self.occurrence_in(&Local, date)
+    }
+
+    // `occurrence_on` in any time zone, so that DST changes can be tested
+    // without depending on the zone of the machine.
+    fn occurrence_in<Tz: TimeZone>(&self, tz: &Tz, date: NaiveDate) -> Option<DateTime<Tz>> {
+        let on_day = match self.mode {
+            AlarmMode::OneTime => true,
+            AlarmMode::Recurring(mask) => mask.contains_dow(date.weekday()),
+            AlarmMode::Dated(d) => d.to_naive() == Some(date),
+        };
+        if !on_day {
+            return None;
+        }
+
+        let naive = date.and_hms_opt(self.time.hour as u32, self.time.min as u32, 0)?;
+        match tz.from_local_datetime(&naive) {
+            LocalResult::Single(dt) => Some(dt),
+            LocalResult::Ambiguous(earliest, _) => Some(earliest),
+            LocalResult::None => (1..=4)
+                .map(|quarters| naive + Duration::minutes(15 * quarters))
+                .find_map(|shifted| tz.from_local_datetime(&shifted).earliest()),
+        }
+    }
+
+    /// The first time after `after` that the alarm will go off, taking a
+    /// skipped occurrence into account. `None` if the alarm is disabled or
+    /// has no days selected.
+    pub fn next_occurrence(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+        if !self.enabled {
+            return None;
+        }
+
+        if let AlarmMode::Dated(d) = self.mode {
+            return self.occurrence_on(d.to_naive()?).filter(|occurrence| occurrence > after);
+        }
+
+        let n_skipped = if self.skip_next { 1 } else { 0 };
+        let mut date = after.date_naive();
+        let mut occurrences = Vec::new();
+        // two weeks always contain at least two occurrences of any alarm that has one
+        for _ in 0..15 {
+            if let Some(occurrence) = self.occurrence_on(date) {
+                if occurrence > *after {
+                    occurrences.push(occurrence);
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        occurrences.into_iter().nth(n_skipped)
+    }
+
+    /// The latest occurrence in the window `(max(last_tick, now - grace), now]`
+    /// that has not gone off yet, if any.
+    pub fn due_occurrence(&self, last_tick: &DateTime<Local>, now: &DateTime<Local>, grace: Duration) -> Option<DateTime<Local>> {
+        if !self.enabled {
+            return None;
+        }
+
+        let window_start = (*last_tick).max(*now - grace);
+        let mut date = now.date_naive();
+        while date >= window_start.date_naive() {
+            if let Some(occurrence) = self.occurrence_on(date) {
+                let fired = self.last_fired.is_some_and(|t| occurrence.timestamp() <= t);
+                if occurrence > window_start && occurrence <= *now && !fired {
+                    return Some(occurrence);
+                }
+            }
+            date = date.pred_opt()?;
+        }
+        None
+    }
+
+    /// Carry over which occurrences of `previous` have gone off, for an
+    /// alarm that replaces it with new settings.
+    pub fn inherit_last_fired(&mut self, previous: &Alarm) {
+        self.last_fired = previous.last_fired;
+    }
+
+    /// Mark `occurrence` of the alarm as reached so that it does not go off
+    /// again. Returns `false` if the occurrence is skipped, in which case the
+    /// skip is cleared.
+    pub fn start(&mut self, occurrence: &DateTime<Local>) -> bool {
+        self.last_fired = Some(occurrence.timestamp());
+        if self.skip_next {
+            self.skip_next = false;
             return false;
         }
+        match self.mode {
+            AlarmMode::OneTime|AlarmMode::Dated(_) => self.enabled = false,
+            AlarmMode::Recurring(_) => ()
+        }
+        true
+    }
 
+    /// Whether this is an enabled alarm for a date that has passed at
+    /// `datetime`.
+    pub fn is_expired(&self, datetime: &DateTime<Local>) -> bool {
         match self.mode {
-            AlarmMode::OneTime => true,
-            AlarmMode::Recurring(mask) => mask.contains_dow(datetime.weekday()),
+            AlarmMode::Dated(date) if self.enabled => match date.to_naive() {
+                Some(d) => self.occurrence_on(d).is_none_or(|occurrence| occurrence <= *datetime),
+                None => true,
+            },
+            _ => false,
         }
     }
+}
 
-    pub fn start(&mut self) {
-        if let AlarmMode::OneTime = self.mode {
-            self.enabled = false;
+#[cfg(test)]
+mod tests {
+    use self::chrono::{FixedOffset, NaiveDateTime, Timelike};
+    use super::*;
+    use test_util::{alarm_at_seven, on};
+
+    fn grace() -> Duration {
+        Duration::minutes(5)
+    }
+
+    fn weekday_alarm() -> Alarm {
+        alarm_at_seven(AlarmMode::Recurring(DayMask::default()))
+    }
+
+    // Central European time with the DST changes of 2024: on 31 March the
+    // clocks go from 02:00 to 03:00, on 27 October from 03:00 back to 02:00.
+    #[derive(Clone, Copy)]
+    struct Cet2024;
+
+    fn offset(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    impl TimeZone for Cet2024 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet2024
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // a local time exists with each offset that maps it to a UTC time which has that offset
+            let fits = |o: FixedOffset| {
+                let utc = *local - Duration::seconds(o.local_minus_utc() as i64);
+                self.offset_from_utc_datetime(&utc) == o
+            };
+            match (fits(offset(2)), fits(offset(1))) {
+                (true, true) => LocalResult::Ambiguous(offset(2), offset(1)),
+                (true, false) => LocalResult::Single(offset(2)),
+                (false, true) => LocalResult::Single(offset(1)),
+                (false, false) => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let utc_at = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            if *utc >= utc_at(3, 31) && *utc < utc_at(10, 27) { offset(2) } else { offset(1) }
+        }
+    }
+
+    #[test]
+    fn missed_alarm_goes_off_within_the_grace_time() {
+        let alarm = weekday_alarm();
+        // no tick happened at exactly 07:00:00
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 59, 58), &on(8, 7, 0, 3), grace()), Some(on(8, 7, 0, 0)));
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 50, 0), &on(8, 7, 4, 59), grace()), Some(on(8, 7, 0, 0)));
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 50, 0), &on(8, 6, 59, 59), grace()), None);
+    }
+
+    #[test]
+    fn missed_alarm_does_not_go_off_after_the_grace_time() {
+        let alarm = weekday_alarm();
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 50, 0), &on(8, 7, 5, 1), grace()), None);
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 50, 0), &on(8, 12, 0, 0), grace()), None);
+    }
+
+    #[test]
+    fn each_occurrence_goes_off_once() {
+        let mut alarm = weekday_alarm();
+        let occurrence = alarm.due_occurrence(&on(8, 6, 59, 59), &on(8, 7, 0, 0), grace()).unwrap();
+        assert!(alarm.start(&occurrence));
+
+        // not even if the window is looked at again, e.g. after a restart
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 59, 0), &on(8, 7, 1, 0), grace()), None);
+        assert_eq!(alarm.next_occurrence(&on(8, 7, 0, 0)), Some(on(9, 7, 0, 0)));
+        assert_eq!(alarm.due_occurrence(&on(9, 6, 59, 59), &on(9, 7, 0, 0), grace()), Some(on(9, 7, 0, 0)));
+    }
+
+    #[test]
+    fn disabled_alarm_never_goes_off() {
+        let mut alarm = weekday_alarm();
+        alarm.set_enabled(false);
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 59, 59), &on(8, 7, 0, 0), grace()), None);
+        assert_eq!(alarm.next_occurrence(&on(8, 0, 0, 0)), None);
+    }
+
+    #[test]
+    fn dated_alarm_goes_off_on_its_date_only() {
+        let mut alarm = alarm_at_seven(AlarmMode::Dated(Date::try_from("2024-01-10").unwrap()));
+        assert_eq!(alarm.next_occurrence(&on(8, 0, 0, 0)), Some(on(10, 7, 0, 0)));
+        assert_eq!(alarm.due_occurrence(&on(9, 6, 59, 59), &on(9, 7, 0, 0), grace()), None);
+
+        let occurrence = alarm.due_occurrence(&on(10, 6, 59, 59), &on(10, 7, 0, 0), grace()).unwrap();
+        assert_eq!(occurrence, on(10, 7, 0, 0));
+        assert!(alarm.start(&occurrence));
+        assert!(!alarm.is_enabled());
+        assert_eq!(alarm.next_occurrence(&on(10, 7, 0, 0)), None);
+    }
+
+    #[test]
+    fn skipped_occurrence_does_not_go_off_but_the_next_one_does() {
+        let mut alarm = weekday_alarm();
+        alarm.set_skip_next(true);
+        assert_eq!(alarm.next_occurrence(&on(8, 0, 0, 0)), Some(on(9, 7, 0, 0)));
+
+        // Monday's occurrence is reached, but skipped
+        let occurrence = alarm.due_occurrence(&on(8, 6, 59, 59), &on(8, 7, 0, 0), grace()).unwrap();
+        assert!(!alarm.start(&occurrence));
+        assert!(!alarm.is_skipping_next());
+        assert!(alarm.is_enabled());
+        assert_eq!(alarm.due_occurrence(&on(8, 6, 59, 59), &on(8, 7, 1, 0), grace()), None);
+
+        // and Tuesday's goes off
+        assert_eq!(alarm.next_occurrence(&on(8, 7, 0, 0)), Some(on(9, 7, 0, 0)));
+        let occurrence = alarm.due_occurrence(&on(9, 6, 59, 59), &on(9, 7, 0, 0), grace()).unwrap();
+        assert!(alarm.start(&occurrence));
+    }
+
+    #[test]
+    fn alarm_in_the_spring_forward_gap_goes_off_after_it() {
+        let alarm = Alarm::new("Test".to_string(), true, Time::new(2, 30).unwrap(), 60, 0.1, 0.7, AlarmMode::OneTime);
+        let occurrence = alarm.occurrence_in(&Cet2024, NaiveDate::from_ymd_opt(2024, 3, 31).unwrap()).unwrap();
+        assert_eq!((occurrence.hour(), occurrence.minute()), (3, 0));
+        assert_eq!(occurrence.offset(), &offset(2));
+    }
+
+    #[test]
+    fn alarm_in_the_fall_back_overlap_goes_off_the_first_time() {
+        let alarm = Alarm::new("Test".to_string(), true, Time::new(2, 30).unwrap(), 60, 0.1, 0.7, AlarmMode::OneTime);
+        let occurrence = alarm.occurrence_in(&Cet2024, NaiveDate::from_ymd_opt(2024, 10, 27).unwrap()).unwrap();
+        assert_eq!((occurrence.hour(), occurrence.minute()), (2, 30));
+        assert_eq!(occurrence.offset(), &offset(2));
+
+        // the day before and after are unaffected
+        for &(month, day, hours) in &[(3, 30, 1), (4, 1, 2), (10, 26, 2), (10, 28, 1)] {
+            let occurrence = alarm.occurrence_in(&Cet2024, NaiveDate::from_ymd_opt(2024, month, day).unwrap()).unwrap();
+            assert_eq!((occurrence.hour(), occurrence.minute()), (2, 30));
+            assert_eq!(occurrence.offset(), &offset(hours));
         }
     }
 }
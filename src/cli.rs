@@ -0,0 +1,89 @@
+use config::Frontend;
+use logging::Level;
+
+pub const USAGE: &str = "Usage: wump [OPTIONS]
+
+Options:
+  -c, --config <path>      Config file to use [default: wump.conf]
+      --mpd-host <host>    Host MPD is running on
+      --mpd-port <port>    Port MPD is listening on
+      --web-bind <addr>    Address the web UI listens on, e.g. 0.0.0.0:8000
+      --log-level <level>  One of error, warn, info or debug
+      --no-hardware        Use mock input and display instead of the hardware
+      --sim                Run in the terminal simulator
+      --fake-player        Don't use MPD, only log what would have been played
+      --silent-player      Like --fake-player, but never start playing
+      --speed <factor>     Run time <factor> times faster than the wall clock
+      --fast-forward       Don't wait at all between updates
+  -h, --help               Show this message
+
+Apart from --config and the development options at the end, each option
+overrides the config file setting of the same name, with dashes replaced by
+underscores. --no-hardware and --sim correspond to the `frontend` setting.";
+
+/// Options given on the command line. Settings that are left out fall back
+/// to the config file.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub config_path:   Option<String>,
+    pub mpd_host:      Option<String>,
+    pub mpd_port:      Option<u16>,
+    pub web_bind:      Option<String>,
+    pub log_level:     Option<Level>,
+    pub frontend:      Option<Frontend>,
+    pub fake_player:   bool,
+    pub silent_player: bool,
+    pub speed:         Option<f64>,
+    pub fast_forward:  bool,
+    pub help:          bool,
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+        let mut opts = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.find('=') {
+                Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value.clone().or_else(|| args.next()).ok_or(format!("{} requires a value", flag))
+            };
+
+            match flag.as_str() {
+                "-c" | "--config" => opts.config_path = Some(value()?),
+                "--mpd-host" => opts.mpd_host = Some(value()?),
+                "--mpd-port" => opts.mpd_port = Some(parse_value(&flag, &value()?)?),
+                "--web-bind" => opts.web_bind = Some(value()?),
+                "--log-level" => opts.log_level = Some(parse_value(&flag, &value()?)?),
+                "--no-hardware" => opts.frontend = Some(Frontend::Mock),
+                "--sim" => opts.frontend = Some(Frontend::Simulator),
+                "--fake-player" => opts.fake_player = true,
+                "--silent-player" => opts.silent_player = true,
+                "--speed" => {
+                    let raw = value()?;
+                    let speed: f64 = parse_value(&flag, &raw)?;
+                    if !speed.is_finite() || speed <= 0.0 {
+                        return Err(format!("invalid value '{}' for {}: must be a positive number", raw, flag));
+                    }
+                    opts.speed = Some(speed);
+                }
+                "--fast-forward" => opts.fast_forward = true,
+                "-h" | "--help" => opts.help = true,
+                _ => return Err(format!("unknown option '{}'", flag)),
+            }
+        }
+
+        Ok(opts)
+    }
+}
+
+fn parse_value<T>(flag: &str, value: &str) -> Result<T, String>
+where T: ::std::str::FromStr,
+      T::Err: ::std::fmt::Display {
+    value.parse().map_err(|e| format!("invalid value '{}' for {}: {}", value, flag, e))
+}
@@ -0,0 +1,110 @@
+extern crate chrono;
+
+use self::chrono::{DateTime, Duration, Local};
+use std::sync::Mutex;
+use std::time;
+use std::time::Instant;
+
+/// Source of the current time, so that alarms and fades can be run against
+/// something other than the wall clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Local>;
+
+    /// How long to wait on the wall clock for `duration` to pass on this
+    /// clock. A clock that isn't driven by the wall clock jumps ahead by
+    /// `duration` instead, and returns zero.
+    fn real_timeout(&self, duration: time::Duration) -> time::Duration;
+}
+
+/// The wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+
+    fn real_timeout(&self, duration: time::Duration) -> time::Duration {
+        duration
+    }
+}
+
+/// A clock that starts at a given time and runs `speed` times faster than
+/// the wall clock.
+pub struct AcceleratedClock {
+    start:      DateTime<Local>,
+    real_start: Instant,
+    speed:      f64,
+}
+
+impl AcceleratedClock {
+    pub fn new(start: DateTime<Local>, speed: f64) -> AcceleratedClock {
+        AcceleratedClock { start, real_start: Instant::now(), speed }
+    }
+}
+
+impl Clock for AcceleratedClock {
+    fn now(&self) -> DateTime<Local> {
+        let elapsed = self.real_start.elapsed().as_secs_f64() * self.speed;
+        self.start + Duration::milliseconds((elapsed * 1000.0) as i64)
+    }
+
+    fn real_timeout(&self, duration: time::Duration) -> time::Duration {
+        duration.div_f64(self.speed)
+    }
+}
+
+/// A clock that only moves when told to. Waiting advances it instantly, so
+/// a loop driven by it runs through days in moments.
+pub struct ManualClock {
+    now: Mutex<DateTime<Local>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Local>) -> ManualClock {
+        ManualClock { now: Mutex::new(start) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Local> {
+        *self.now.lock().unwrap()
+    }
+
+    fn real_timeout(&self, duration: time::Duration) -> time::Duration {
+        self.advance(Duration::from_std(duration).unwrap_or_else(|_| Duration::zero()));
+        time::Duration::from_millis(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::at;
+
+    #[test]
+    fn manual_clock_only_moves_when_told() {
+        let clock = ManualClock::new(at(7, 0, 0));
+        assert_eq!(clock.now(), at(7, 0, 0));
+        assert_eq!(clock.now(), at(7, 0, 0));
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(clock.now(), at(7, 5, 0));
+    }
+
+    #[test]
+    fn manual_clock_jumps_ahead_instead_of_waiting() {
+        let clock = ManualClock::new(at(7, 0, 0));
+        let real_start = Instant::now();
+
+        let real = clock.real_timeout(time::Duration::from_secs(7 * 24 * 60 * 60));
+        assert_eq!(real, time::Duration::from_millis(0));
+        assert_eq!(clock.now(), at(7, 0, 0) + Duration::weeks(1));
+        assert!(real_start.elapsed() < time::Duration::from_secs(1));
+    }
+}
@@ -1,16 +1,208 @@
 extern crate serde_json;
 extern crate serde;
+extern crate chrono;
 
 use self::serde::{Deserialize, Serialize};
+use self::chrono::{DateTime, Duration, Local};
+use self::serde_json::{Map, Value};
 
+use std::convert::TryFrom;
+use std::fs;
 use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::path::Path;
 
 use alarm::Alarm;
+use hardware::HardwareConfig;
+use logging::Level;
+use migration;
+use migration::CONFIG_VERSION;
+use validation::{bounded_seconds, check, ValidationError};
 
-#[derive(Default, Serialize, Deserialize)]
+// Upper limits of the settings, so that the times computed from them stay
+// within what chrono can represent
+const MAX_TRIGGER_GRACE_S: i64 = 60 * 60;
+const MAX_FALLBACK_DELAY_S: i64 = 60 * 60;
+const MAX_SLEEP_FADE_S: i64 = 24 * 60 * 60;
+/// Longest sleep timer, from the buttons or the web UI.
+pub const MAX_SLEEP_TIMER_MIN: i64 = 24 * 60;
+
+/// Where input is read from and the display is shown.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frontend {
+    #[default]
+    Hardware,
+    Mock,
+    Simulator,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
-    pub alarm: Alarm
+    // Format of the file, see `migration`
+    #[serde(default)]
+    pub version:          u64,
+    #[serde(default = "default_alarms")]
+    pub alarms:           Vec<Alarm>,
+    // How late an alarm may still go off if its exact time was missed
+    #[serde(default = "default_trigger_grace_s")]
+    pub trigger_grace_s:  i64,
+    // Length of a sleep timer started from the buttons
+    #[serde(default = "default_sleep_timer_min")]
+    pub sleep_timer_min:  i64,
+    #[serde(default = "default_sleep_fade_s")]
+    pub sleep_fade_s:     i64,
+    // How long an alarm may fail to start playing before the fallback tone
+    // sounds, and the command the tone is piped to as a WAV stream
+    #[serde(default = "default_fallback_delay_s")]
+    pub fallback_delay_s: i64,
+    #[serde(default = "default_fallback_command")]
+    pub fallback_command: String,
+    // Startup settings, each of which can be overridden on the command line
+    #[serde(default = "default_mpd_host")]
+    pub mpd_host:         String,
+    #[serde(default = "default_mpd_port")]
+    pub mpd_port:         u16,
+    #[serde(default = "default_web_bind")]
+    pub web_bind:         String,
+    #[serde(default)]
+    pub log_level:        Level,
+    #[serde(default)]
+    pub frontend:         Frontend,
+    #[serde(default)]
+    pub hardware:         HardwareConfig,
+}
+
+fn default_alarms() -> Vec<Alarm> {
+    vec![Alarm::default()]
+}
+
+fn default_trigger_grace_s() -> i64 {
+    5 * 60
+}
+
+fn default_sleep_timer_min() -> i64 {
+    30
+}
+
+fn default_sleep_fade_s() -> i64 {
+    60
+}
+
+fn default_fallback_delay_s() -> i64 {
+    10
+}
+
+fn default_fallback_command() -> String {
+    "aplay -q -".to_string()
+}
+
+fn default_mpd_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_mpd_port() -> u16 {
+    6600
+}
+
+fn default_web_bind() -> String {
+    "0.0.0.0:8000".to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            version:          CONFIG_VERSION,
+            alarms:           default_alarms(),
+            trigger_grace_s:  default_trigger_grace_s(),
+            sleep_timer_min:  default_sleep_timer_min(),
+            sleep_fade_s:     default_sleep_fade_s(),
+            fallback_delay_s: default_fallback_delay_s(),
+            fallback_command: default_fallback_command(),
+            mpd_host:         default_mpd_host(),
+            mpd_port:         default_mpd_port(),
+            web_bind:         default_web_bind(),
+            log_level:        Level::default(),
+            frontend:         Frontend::default(),
+            hardware:         HardwareConfig::default(),
+        }
+    }
+}
+
+/// Reads the config from the (migrated) contents of a config file, checking
+/// its settings.
+impl TryFrom<Value> for Config {
+    type Error = ValidationError;
+
+    fn try_from(value: Value) -> Result<Config, ValidationError> {
+        let mut fields = match value {
+            Value::Object(fields) => fields,
+            _ => return Err(ValidationError::Syntax("the config is not a JSON object".to_string())),
+        };
+
+        let defaulted = missing_settings(&fields);
+        if !defaulted.is_empty() {
+            info!("Settings missing from the config were given default values: {}", defaulted.join(", "));
+        }
+
+        // read the alarms one at a time, so that an error tells which one is at fault
+        let alarms = match fields.remove("alarms") {
+            Some(Value::Array(alarms)) => alarms
+                .into_iter()
+                .enumerate()
+                .map(|(index, a)| Alarm::try_from(a).map_err(|e| ValidationError::Alarm { index, error: Box::new(e) }))
+                .collect::<Result<Vec<Alarm>, ValidationError>>()?,
+            Some(other) => return Err(ValidationError::Syntax(format!("alarms is {}, expected a list", other))),
+            None => default_alarms(),
+        };
+
+        let mut config: Config = serde_json::from_value(Value::Object(fields))?;
+        config.alarms = alarms;
+        config.check()?;
+        Ok(config)
+    }
+}
+
+// Names the settings of a config file read as JSON that will be filled in
+// with their defaults, including those of each alarm.
+fn missing_settings(fields: &Map<String, Value>) -> Vec<String> {
+    fn missing(fields: &Map<String, Value>, defaults: &Value, prefix: &str) -> Vec<String> {
+        match *defaults {
+            Value::Object(ref defaults) => defaults
+                .keys()
+                .filter(|key| !fields.contains_key(*key))
+                .map(|key| format!("{}{}", prefix, key))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    let config_defaults = serde_json::to_value(Config::default()).unwrap_or(Value::Null);
+    let mut names = missing(fields, &config_defaults, "");
+    if let Some(Value::Array(alarms)) = fields.get("alarms") {
+        let alarm_defaults = serde_json::to_value(Alarm::default()).unwrap_or(Value::Null);
+        for (index, alarm) in alarms.iter().enumerate() {
+            if let Value::Object(ref alarm) = *alarm {
+                names.extend(missing(alarm, &alarm_defaults, &format!("alarm {} ", index + 1)));
+            }
+        }
+    }
+    names
+}
+
+/// Where the previous version of the config file at `fname` is kept.
+pub fn backup_path(fname: &str) -> String {
+    format!("{}.bak", fname)
+}
+
+// Makes a rename in the directory holding `fname` survive a power cut.
+fn sync_parent_dir(fname: &str) -> io::Result<()> {
+    let dir = match Path::new(fname).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
 }
 
 impl Config {
@@ -18,14 +210,33 @@ impl Config {
         let file = File::open(fname)?;
         let reader = io::BufReader::new(file);
 
-        *self = serde_json::from_reader(reader)?;
+        let value = migration::migrate(serde_json::from_reader(reader)?)?;
+        *self = Config::try_from(value)?;
         Ok(())
     }
 
+    /// Replaces the file at `fname` so that it holds either the old or the
+    /// new config if the power is cut along the way. The new config is
+    /// written to a temporary file first, the old one is kept as a backup,
+    /// and the temporary file is then renamed into place.
     pub fn write(&self, fname: &str) -> io::Result<()> {
-        let file = File::create(fname)?;
-        let writer = io::BufWriter::new(file);
-        serde_json::to_writer_pretty(writer,self).map_err(|e| e.into())
+        let tmp_fname = format!("{}.tmp", fname);
+        {
+            let file = File::create(&tmp_fname)?;
+            let mut writer = io::BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+
+        if Path::new(fname).exists() {
+            let backup_fname = backup_path(fname);
+            fs::copy(fname, &backup_fname)?;
+            File::open(&backup_fname)?.sync_all()?;
+        }
+
+        fs::rename(&tmp_fname, fname)?;
+        sync_parent_dir(fname)
     }
 
     pub fn read_new(fname: &str) -> io::Result<Config> {
@@ -34,4 +245,86 @@ impl Config {
         conf.read(fname)?;
         Ok(conf)
     }
+
+    /// Checks that the settings, including those of every alarm, are within
+    /// their ranges.
+    pub fn check(&self) -> Result<(), ValidationError> {
+        check(self.trigger_grace_s >= 0 && self.trigger_grace_s <= MAX_TRIGGER_GRACE_S, "trigger grace time",
+              format!("{} s", self.trigger_grace_s), "zero to 1 hour")?;
+        check(self.sleep_timer_min > 0 && self.sleep_timer_min <= MAX_SLEEP_TIMER_MIN, "sleep timer",
+              format!("{} min", self.sleep_timer_min), "more than zero, up to 24 hours")?;
+        check(self.sleep_fade_s >= 0 && self.sleep_fade_s <= MAX_SLEEP_FADE_S, "sleep fade length",
+              format!("{} s", self.sleep_fade_s), "zero to 24 hours")?;
+        check(self.fallback_delay_s >= 0 && self.fallback_delay_s <= MAX_FALLBACK_DELAY_S, "fallback delay",
+              format!("{} s", self.fallback_delay_s), "zero to 1 hour")?;
+        for (index, alarm) in self.alarms.iter().enumerate() {
+            alarm.check().map_err(|e| ValidationError::Alarm { index, error: Box::new(e) })?;
+        }
+        Ok(())
+    }
+
+    pub fn get_trigger_grace(&self) -> Duration {
+        bounded_seconds(self.trigger_grace_s, MAX_TRIGGER_GRACE_S)
+    }
+
+    pub fn get_sleep_timer(&self) -> Duration {
+        bounded_seconds(self.sleep_timer_min.saturating_mul(60), MAX_SLEEP_TIMER_MIN * 60)
+    }
+
+    pub fn get_sleep_fade(&self) -> Duration {
+        bounded_seconds(self.sleep_fade_s, MAX_SLEEP_FADE_S)
+    }
+
+    pub fn get_fallback_delay(&self) -> Duration {
+        bounded_seconds(self.fallback_delay_s, MAX_FALLBACK_DELAY_S)
+    }
+
+    pub fn get_fallback_command(&self) -> &str {
+        &self.fallback_command
+    }
+
+    pub fn get_web_bind(&self) -> &str {
+        &self.web_bind
+    }
+
+    pub fn get_log_level(&self) -> Level {
+        self.log_level
+    }
+
+    pub fn get_frontend(&self) -> Frontend {
+        self.frontend
+    }
+
+    pub fn get_hardware(&self) -> &HardwareConfig {
+        &self.hardware
+    }
+
+    /// Disable dated alarms that passed before `cutoff` without going off.
+    /// Returns whether any alarm was changed.
+    pub fn disable_expired(&mut self, cutoff: &DateTime<Local>) -> bool {
+        let mut changed = false;
+        for alarm in self.alarms.iter_mut().filter(|a| a.is_expired(cutoff)) {
+            info!("Disabling alarm '{}' since its date has passed", alarm.get_name());
+            alarm.set_enabled(false);
+            changed = true;
+        }
+        changed
+    }
+
+    /// Index of the enabled alarm that goes off next after `now`.
+    pub fn next_alarm(&self, now: &DateTime<Local>) -> Option<usize> {
+        self.alarms
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| a.next_occurrence(now).map(|t| (t, i)))
+            .min()
+            .map(|(_, i)| i)
+    }
+
+    /// Index of the alarm shown on the display and toggled by the alarm
+    /// button, unless the button was just used: the next one to go off, or
+    /// the first one if none is enabled.
+    pub fn displayed_alarm(&self, now: &DateTime<Local>) -> Option<usize> {
+        self.next_alarm(now).or(if self.alarms.is_empty() { None } else { Some(0) })
+    }
 }
@@ -0,0 +1,155 @@
+extern crate chrono;
+extern crate serde;
+
+use self::chrono::{DateTime, Duration, Local};
+use self::serde::{Deserialize, Serialize};
+
+use alarm::Alarm;
+
+// Steepness of the exponential curve. The volume at the midpoint of the fade
+// is about 12% of the way from start to end volume.
+const EXPONENTIAL_STEEPNESS: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    // Slow start and fast end, which sounds more even than a linear ramp
+    // since perceived loudness is roughly logarithmic in the volume.
+    Exponential,
+    // Slow start and slow end (smoothstep)
+    SCurve,
+    // Constant volume between a number of evenly spaced jumps
+    Stepped(u8),
+}
+
+impl FadeCurve {
+    /// Maps the elapsed fraction `a` of the fade onto the fraction of the
+    /// way from start to end volume. Both are in `0.0..=1.0`.
+    pub fn apply(&self, a: f32) -> f32 {
+        let a = a.clamp(0.0, 1.0);
+        match *self {
+            FadeCurve::Linear => a,
+            FadeCurve::Exponential => (EXPONENTIAL_STEEPNESS * a).exp_m1() / EXPONENTIAL_STEEPNESS.exp_m1(),
+            FadeCurve::SCurve => a * a * (3.0 - 2.0 * a),
+            FadeCurve::Stepped(n_steps) => {
+                let n_steps = n_steps.max(1) as f32;
+                (a * n_steps).floor() / n_steps
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Fade {
+    start_time: DateTime<Local>,
+    end_time:   DateTime<Local>,
+    start_vol:  f32,
+    end_vol:    f32,
+    curve:      FadeCurve,
+    // Fading out runs the curve backwards so that it mirrors the fade in
+    reverse:    bool,
+}
+
+impl Fade {
+    pub fn new(start: DateTime<Local>, alarm: &Alarm) -> Fade {
+        Fade {
+            start_time: start,
+            end_time:   start + alarm.get_length(),
+            start_vol:  alarm.get_start_vol(),
+            end_vol:    alarm.get_end_vol(),
+            curve:      alarm.get_fade_curve(),
+            reverse:    false,
+        }
+    }
+
+    /// A fade from `from_vol` down to silence.
+    pub fn fade_out(start: DateTime<Local>, length: Duration, from_vol: f32, curve: FadeCurve) -> Fade {
+        Fade { start_time: start, end_time: start + length, start_vol: from_vol, end_vol: 0.0, curve, reverse: true }
+    }
+
+    pub fn get_start_vol(&self) -> f32 {
+        self.start_vol
+    }
+
+    /// Fraction of the fade that has elapsed at `now`, in `0.0..=1.0`.
+    pub fn progress(&self, now: &DateTime<Local>) -> f32 {
+        let num = (*now - self.start_time).num_milliseconds() as f32;
+        let den = (self.end_time - self.start_time).num_milliseconds() as f32;
+        if den <= 0.0 {
+            return 1.0;
+        }
+        (num / den).clamp(0.0, 1.0)
+    }
+
+    /// The volume in percent to use at `now`.
+    pub fn volume_at(&self, now: &DateTime<Local>) -> i8 {
+        let vol_fraction = if self.reverse {
+            self.end_vol + (self.start_vol - self.end_vol) * self.curve.apply(1.0 - self.progress(now))
+        } else {
+            self.start_vol + (self.end_vol - self.start_vol) * self.curve.apply(self.progress(now))
+        };
+        (vol_fraction * 100.0).round() as i8
+    }
+
+    pub fn is_done(&self, now: &DateTime<Local>) -> bool {
+        *now >= self.end_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::at;
+
+    const CURVES: &[FadeCurve] =
+        &[FadeCurve::Linear, FadeCurve::Exponential, FadeCurve::SCurve, FadeCurve::Stepped(4)];
+
+    fn start() -> DateTime<Local> {
+        at(12, 0, 0)
+    }
+
+    #[test]
+    fn curves_go_from_start_to_end() {
+        for curve in CURVES {
+            assert_eq!(curve.apply(0.0), 0.0, "{:?}", curve);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_never_decrease() {
+        for curve in CURVES {
+            let values: Vec<f32> = (0..=100).map(|i| curve.apply(i as f32 / 100.0)).collect();
+            assert!(values.windows(2).all(|w| w[1] >= w[0]), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn stepped_curve_jumps_at_step_boundaries() {
+        let curve = FadeCurve::Stepped(4);
+        assert_eq!(curve.apply(0.24), 0.0);
+        assert_eq!(curve.apply(0.25), 0.25);
+        assert_eq!(curve.apply(0.49), 0.25);
+        assert_eq!(curve.apply(0.5), 0.5);
+        assert_eq!(curve.apply(0.99), 0.75);
+    }
+
+    #[test]
+    fn stepped_curve_with_zero_steps_is_one_step() {
+        assert_eq!(FadeCurve::Stepped(0).apply(0.5), 0.0);
+        assert_eq!(FadeCurve::Stepped(0).apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn fade_out_goes_from_start_volume_to_silence() {
+        for &curve in CURVES {
+            let fade = Fade::fade_out(start(), Duration::seconds(60), 0.6, curve);
+            assert_eq!(fade.volume_at(&start()), 60, "{:?}", curve);
+            assert_eq!(fade.volume_at(&(start() + Duration::seconds(60))), 0, "{:?}", curve);
+
+            let volumes: Vec<i8> = (0..=60).map(|s| fade.volume_at(&(start() + Duration::seconds(s)))).collect();
+            assert!(volumes.windows(2).all(|w| w[1] <= w[0]), "{:?}", curve);
+        }
+    }
+}
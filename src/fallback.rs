@@ -0,0 +1,113 @@
+use std::f32::consts::PI;
+use std::io;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+// The tone sequence: a few short beeps followed by a pause, repeated
+const SAMPLE_RATE: u32 = 22050;
+const BEEP_FREQ_HZ: f32 = 880.0;
+const BEEP_MS: u32 = 150;
+const GAP_MS: u32 = 100;
+const PAUSE_MS: u32 = 700;
+const N_BEEPS: u32 = 3;
+const AMPLITUDE: f32 = 0.5 * 32767.0;
+
+/// One period of the tone sequence, as 16 bit mono samples.
+pub fn tone_sequence() -> Vec<i16> {
+    let n_samples = |ms: u32| (SAMPLE_RATE * ms / 1000) as usize;
+    let mut samples = Vec::new();
+
+    for _ in 0..N_BEEPS {
+        let beep_len = n_samples(BEEP_MS);
+        samples.extend((0..beep_len).map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            (AMPLITUDE * (2.0 * PI * BEEP_FREQ_HZ * t).sin()) as i16
+        }));
+        samples.extend(vec![0; n_samples(GAP_MS)]);
+    }
+    samples.extend(vec![0; n_samples(PAUSE_MS)]);
+    samples
+}
+
+/// Header of a WAV file holding `n_samples` 16 bit mono samples.
+pub fn wav_header(n_samples: u32) -> Vec<u8> {
+    let data_len = n_samples * 2;
+    let mut header = Vec::with_capacity(44);
+
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes()); // size of the format chunk
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // bytes per second
+    header.extend_from_slice(&2u16.to_le_bytes()); // bytes per frame
+    header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    header
+}
+
+/// Sounds the built-in tone sequence, for when an alarm goes off and MPD
+/// can't play it. The tone is written as a WAV stream to the standard input
+/// of `command`, e.g. `aplay -q -`.
+pub struct FallbackAlarm {
+    command: String,
+    child:   Option<Child>,
+}
+
+impl FallbackAlarm {
+    pub fn new(command: &str) -> FallbackAlarm {
+        FallbackAlarm { command: command.to_string(), child: None }
+    }
+
+    /// Starts or stops the tone.
+    pub fn set_active(&mut self, active: bool) {
+        if active && self.child.is_none() {
+            info!("Playing the fallback alarm tone through '{}'", self.command);
+            match self.spawn() {
+                Ok(child) => self.child = Some(child),
+                Err(e) => error!("Failed starting the fallback alarm tone: {}", e),
+            }
+        } else if !active {
+            if let Some(mut child) = self.child.take() {
+                info!("Stopping the fallback alarm tone");
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+
+    fn spawn(&self) -> io::Result<Child> {
+        let mut args = self.command.split_whitespace();
+        let program = args.next().unwrap_or("aplay");
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        // stream the sequence over and over until the player goes away
+        let mut stdin = child.stdin.take().unwrap();
+        thread::spawn(move || {
+            let samples = tone_sequence();
+            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+            // announce as much data as a WAV file can hold
+            if stdin.write_all(&wav_header((u32::MAX - 36) / 2)).is_err() {
+                return;
+            }
+            while stdin.write_all(&bytes).is_ok() {}
+        });
+
+        Ok(child)
+    }
+}
+
+impl Drop for FallbackAlarm {
+    fn drop(&mut self) {
+        self.set_active(false);
+    }
+}
@@ -0,0 +1,98 @@
+extern crate rppal;
+
+use self::rppal::gpio::{Gpio, Trigger, InputPin, Level, Pin};
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use hardware::{ButtonConfig, EncoderConfig, Pull};
+use input::{InputEvent, InputQueue, InputSource};
+
+/// Converts a GPIO error for the `io::Result`s used throughout.
+pub fn gpio_error(e: rppal::gpio::Error) -> io::Error {
+    io::Error::other(format!("GPIO error: {}", e))
+}
+
+fn into_input(pin: Pin, pull: Pull) -> InputPin {
+    match pull {
+        Pull::None => pin.into_input(),
+        Pull::Up => pin.into_input_pullup(),
+        Pull::Down => pin.into_input_pulldown(),
+    }
+}
+
+pub struct InputHandler {
+    queue:                  InputQueue,
+    _button_pins:           Vec<InputPin>,
+    _rotary_encoder_thread: thread::JoinHandle<()>,
+}
+
+impl InputHandler {
+    pub fn new(buttons: &[ButtonConfig], encoder: &EncoderConfig) -> io::Result<InputHandler> {
+        let (tx, rx) = mpsc::channel();
+        // let mut mute_state : bool = false;
+        // let mute_pin = Pin::new(MUTE_PIN);
+        // mute_pin.export().expect("Failed exporting mute pin");
+        // set_pin_dir(&mute_pin, Direction::High).expect("Failed setting direction of mute pin");
+
+        // let mut poff_state : bool = false;
+        // let poff_pin = Pin::new(POFF_PIN);
+        // poff_pin.export().expect("Failed exporting power off pin");
+        // set_pin_dir(&poff_pin, Direction::High).expect("Failed setting direction of power off pin");
+
+        let gpio = Gpio::new().map_err(gpio_error)?;
+        let _button_pins = buttons
+            .iter()
+            .map(|button| {
+                let b = button.pin;
+                let mut button_pin = into_input(gpio.get(b).map_err(gpio_error)?, button.pull);
+                // a button pulled up connects the pin to ground when pressed
+                let trigger = if button.pull == Pull::Up { Trigger::FallingEdge } else { Trigger::RisingEdge };
+                let tx_b = tx.clone();
+                button_pin
+                    .set_async_interrupt(trigger, move |_| {
+                        tx_b.send(InputEvent::Button(b)).unwrap();
+                    })
+                    .map_err(gpio_error)?;
+                Ok(button_pin)
+            })
+            .collect::<io::Result<Vec<InputPin>>>()?;
+
+        // claim the encoder pins up front so that a missing pin is reported
+        // here rather than panicking in the polling thread
+        let rotenc_a_pin = into_input(gpio.get(encoder.pin_a).map_err(gpio_error)?, encoder.pull);
+        let rotenc_b_pin = into_input(gpio.get(encoder.pin_b).map_err(gpio_error)?, encoder.pull);
+
+        let _rotary_encoder_thread = thread::spawn(move || {
+            let tx_rotenc = tx.clone();
+            let mut last_clk_state = Level::High;
+
+            loop {
+                let aval = rotenc_a_pin.read();
+                if aval == Level::High && last_clk_state == Level::Low {
+                    if let Level::Low = rotenc_b_pin.read() {
+                        tx_rotenc.send(InputEvent::RotaryEncoder(1)).unwrap();
+                    } else {
+                        tx_rotenc.send(InputEvent::RotaryEncoder(-1)).unwrap();
+                    }
+                }
+                last_clk_state = aval;
+
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        Ok(InputHandler { queue: InputQueue::new(rx), _button_pins, _rotary_encoder_thread })
+    }
+}
+
+impl InputSource for InputHandler {
+    fn handle_events(&mut self, callback: &mut dyn FnMut(InputEvent)) {
+        self.queue.handle_events(callback)
+    }
+
+    fn start(&mut self, callback: Box<dyn Fn(InputEvent) + Send>) {
+        self.queue.start(callback)
+    }
+}
@@ -0,0 +1,109 @@
+extern crate serde;
+
+use self::serde::{Deserialize, Serialize};
+
+// Pin usage of Hifiberry Miniamp:
+// GPIOs 18-21 (pins 12, 35, 38 and 40) are used for the sound
+// interface. GPIO16 can be used to mute the power stage. GPIO26 shuts
+// down the power stage. You can’t use these GPIOs for any other
+// purpose.
+
+// const MUTE_PIN: u8 = 16;
+// const POFF_PIN: u8 = 26;
+
+/// What pressing a button does.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonAction {
+    Alarm,
+    PlayPause,
+    Light,
+    Power,
+}
+
+/// Internal pull resistor of an input pin. Buttons pulled up are taken to
+/// connect the pin to ground and trigger on the falling edge, the others on
+/// the rising edge.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pull {
+    #[default]
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ButtonConfig {
+    pub pin:    u8,
+    pub action: ButtonAction,
+    #[serde(default)]
+    pub pull:   Pull,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EncoderConfig {
+    pub pin_a: u8,
+    pub pin_b: u8,
+    #[serde(default)]
+    pub pull:  Pull,
+}
+
+/// The display is driven through an MCP23S17 port expander on the SPI bus.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DisplayConfig {
+    pub spi_device: String,
+    pub cs_pin:     u8,
+    pub rst_pin:    u8,
+}
+
+/// How the buttons, the rotary encoder and the display are wired up.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HardwareConfig {
+    #[serde(default = "default_buttons")]
+    pub buttons: Vec<ButtonConfig>,
+    #[serde(default = "default_encoder")]
+    pub encoder: EncoderConfig,
+    #[serde(default = "default_display")]
+    pub display: DisplayConfig,
+}
+
+fn default_buttons() -> Vec<ButtonConfig> {
+    let button = |pin, action| ButtonConfig { pin, action, pull: Pull::None };
+    vec![
+        button(27, ButtonAction::Alarm),     // Red
+        button(17, ButtonAction::PlayPause), // Black
+        button(22, ButtonAction::Light),     // White
+        button(4, ButtonAction::Power),      // Rotary encoder
+    ]
+}
+
+fn default_encoder() -> EncoderConfig {
+    EncoderConfig { pin_a: 15, pin_b: 14, pull: Pull::None }
+}
+
+fn default_display() -> DisplayConfig {
+    DisplayConfig {
+        spi_device: "/dev/spidev0.1".to_string(),
+        cs_pin:     7,  // == Pin 26 == CE1
+        rst_pin:    23, // == Pin 16 ==
+    }
+}
+
+impl Default for HardwareConfig {
+    fn default() -> Self {
+        HardwareConfig { buttons: default_buttons(), encoder: default_encoder(), display: default_display() }
+    }
+}
+
+impl HardwareConfig {
+    /// Action of the button on `pin`, if there is one.
+    pub fn get_button_action(&self, pin: u8) -> Option<ButtonAction> {
+        self.buttons.iter().find(|b| b.pin == pin).map(|b| b.action)
+    }
+
+    /// Pin of the first button doing `action`.
+    pub fn get_button_pin(&self, action: ButtonAction) -> Option<u8> {
+        self.buttons.iter().find(|b| b.action == action).map(|b| b.pin)
+    }
+}
@@ -1,9 +1,5 @@
-extern crate rppal;
-
-use self::rppal::gpio::{Gpio, Trigger, InputPin, Level};
 use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
 
 #[derive(Debug, Copy, Clone)]
 pub enum InputEvent {
@@ -11,74 +7,69 @@ pub enum InputEvent {
     RotaryEncoder(i8),
 }
 
-pub struct InputHandler {
-    rx:                     mpsc::Receiver<InputEvent>,
-    _button_pins:           Vec<InputPin>,
-    _rotary_encoder_thread: thread::JoinHandle<()>,
+/// Something that produces button presses and rotary encoder steps.
+pub trait InputSource {
+    /// Calls `callback` for each event that arrived since the last call.
+    fn handle_events(&mut self, callback: &mut dyn FnMut(InputEvent));
+
+    /// From now on, calls `callback` from a background thread as soon as an
+    /// event arrives, instead of keeping it for `handle_events`.
+    fn start(&mut self, callback: Box<dyn Fn(InputEvent) + Send>);
 }
 
-impl InputHandler {
-    pub fn new(button_pin_ids: &[u8], rotary_encoder_pin_ids: (u8, u8)) -> InputHandler {
-        let (tx, rx) = mpsc::channel();
-        // let mut mute_state : bool = false;
-        // let mute_pin = Pin::new(MUTE_PIN);
-        // mute_pin.export().expect("Failed exporting mute pin");
-        // set_pin_dir(&mute_pin, Direction::High).expect("Failed setting direction of mute pin");
+/// Events of an input source, kept until they are handled or forwarded.
+pub struct InputQueue {
+    rx: Option<mpsc::Receiver<InputEvent>>,
+}
 
-        // let mut poff_state : bool = false;
-        // let poff_pin = Pin::new(POFF_PIN);
-        // poff_pin.export().expect("Failed exporting power off pin");
-        // set_pin_dir(&poff_pin, Direction::High).expect("Failed setting direction of power off pin");
+impl InputQueue {
+    pub fn new(rx: mpsc::Receiver<InputEvent>) -> InputQueue {
+        InputQueue { rx: Some(rx) }
+    }
 
-        let gpio = Gpio::new().unwrap();
-        let _button_pins: Vec<InputPin> = button_pin_ids
-            .iter()
-            .map(|&b| {
-                let mut button_pin = gpio.get(b).expect("Failed setting button gpio pin to input").into_input();
-                let tx_b = tx.clone();
-                button_pin
-                    .set_async_interrupt(Trigger::RisingEdge, move |_| {
-                        tx_b.send(InputEvent::Button(b)).unwrap();
-                    })
-                    .unwrap();
-                button_pin
-            })
-            .collect();
+    pub fn handle_events(&mut self, callback: &mut dyn FnMut(InputEvent)) {
+        if let Some(rx) = self.rx.as_ref() {
+            for x in rx.try_iter() {
+                callback(x)
+            }
+        }
+    }
 
-        let _rotary_encoder_thread = thread::spawn(move || {
-            let tx_rotenc = tx.clone();
-            let rotenc_a_pin = gpio
-                .get(rotary_encoder_pin_ids.0)
-                .expect("Failed setting rotary encoder a gpio pin to input")
-                .into_input();
-            let rotenc_b_pin = gpio
-                .get(rotary_encoder_pin_ids.1)
-                .expect("Failed setting rotary encoder b gpio pin to input")
-                .into_input();
+    pub fn start(&mut self, callback: Box<dyn Fn(InputEvent) + Send>) {
+        if let Some(rx) = self.rx.take() {
+            thread::spawn(move || {
+                for x in rx {
+                    callback(x)
+                }
+            });
+        }
+    }
+}
 
-            let mut last_clk_state = Level::High;
+/// Input that only delivers events sent to it through a channel, for
+/// running without the buttons attached.
+pub struct MockInput {
+    tx:    mpsc::Sender<InputEvent>,
+    queue: InputQueue,
+}
 
-            loop {
-                let aval = rotenc_a_pin.read();
-                if aval == Level::High && last_clk_state == Level::Low {
-                    if let Level::Low = rotenc_b_pin.read() {
-                        tx_rotenc.send(InputEvent::RotaryEncoder(1)).unwrap();
-                    } else {
-                        tx_rotenc.send(InputEvent::RotaryEncoder(-1)).unwrap();
-                    }
-                }
-                last_clk_state = aval;
+impl MockInput {
+    pub fn new() -> MockInput {
+        let (tx, rx) = mpsc::channel();
+        MockInput { tx, queue: InputQueue::new(rx) }
+    }
 
-                thread::sleep(Duration::from_millis(1));
-            }
-        });
+    pub fn sender(&self) -> mpsc::Sender<InputEvent> {
+        self.tx.clone()
+    }
+}
 
-        InputHandler { rx, _button_pins, _rotary_encoder_thread }
+impl InputSource for MockInput {
+    fn handle_events(&mut self, callback: &mut dyn FnMut(InputEvent)) {
+        self.queue.handle_events(callback)
     }
 
-    pub fn handle_events(&mut self, mut callback: impl FnMut(InputEvent)) {
-        for x in self.rx.try_iter() {
-            callback(x)
-        }
+    fn start(&mut self, callback: Box<dyn Fn(InputEvent) + Send>) {
+        self.queue.start(callback)
     }
 }
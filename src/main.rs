@@ -5,99 +5,218 @@ extern crate chrono;
 extern crate mpd;
 extern crate signal_hook;
 
+use std::io;
 use std::time::Duration;
 use std::thread;
 use std::cmp::Ordering;
-use std::sync::{RwLock,Arc};
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering as SyncOrdering;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 use chrono::{Local, DateTime};
 
+#[macro_use]
+mod logging;
+mod cli;
 mod display;
 mod input;
 mod alarm;
 mod webui;
 mod config;
+mod fade;
+mod playback;
+mod sim;
+mod clock;
+mod player;
+mod fallback;
+mod store;
+mod migration;
+mod validation;
+mod hardware;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "hardware")]
+mod lcd;
+#[cfg(feature = "hardware")]
+mod gpio_input;
+
+use display::{Display, LcdDevice, MockLcd};
+use input::{InputEvent, InputSource, MockInput};
+use config::Frontend;
+use playback::{Action, Event, StateMachine};
+use sim::{KeyboardInput, TerminalLcd};
+use clock::{Clock, SystemClock, AcceleratedClock, ManualClock};
+use player::{Player, ReconnectingPlayer, FakePlayer, watch_mpd};
+use fallback::FallbackAlarm;
+use webui::{start_webui, WebCommand};
+use hardware::{ButtonAction, HardwareConfig};
+use store::{ConfigStore, watch_config};
+
+const DEFAULT_CONFIG_PATH: &str = "wump.conf";
+
+// How often to try reaching the player again while it is offline
+const PLAYER_RETRY_MS: i64 = 1000;
+
+// Keys standing in for the buttons in the terminal simulator
+const SIM_KEYS: &[(char, ButtonAction)] = &[
+    ('a', ButtonAction::Alarm),
+    ('b', ButtonAction::PlayPause),
+    ('c', ButtonAction::Light),
+    ('p', ButtonAction::Power),
+];
+const SIM_LEGEND: &str = "a: alarm  b: play/pause  c: light  p: power  arrows or +/-: rotate  ctrl-c: quit";
+
+
+#[cfg(feature = "hardware")]
+fn open_hardware(hw: &HardwareConfig) -> io::Result<(Box<dyn InputSource>, Box<dyn LcdDevice>)> {
+    let input = gpio_input::InputHandler::new(&hw.buttons, &hw.encoder)?;
+    let lcd = lcd::Lcd128x64::new(&hw.display)?;
+    Ok((Box::new(input), Box::new(lcd)))
+}
 
-use display::Display;
-use input::{InputHandler,InputEvent};
-use alarm::Alarm;
-use config::Config;
-use webui::start_webui;
-
-// Pin usage of Hifiberry Miniamp:
-// GPIOs 18-21 (pins 12, 35, 38 and 40) are used for the sound
-// interface. GPIO16 can be used to mute the power stage. GPIO26 shuts
-// down the power stage. You can’t use these GPIOs for any other
-// purpose.
-
-// Button pins
-const BUTTON_A: u8   = 27; // Red   (alarm)
-const BUTTON_B: u8   = 17; // Black (play/pause)
-const BUTTON_C: u8   = 22; // White (light)
-const BUTTON_ROT: u8 = 4;  // Rotary encoder
-
-// const MUTE_PIN: u8 = 16;
-// const POFF_PIN: u8 = 26;
-
-const BUTTONS: &[u8] = &[BUTTON_A, BUTTON_B, BUTTON_C, BUTTON_ROT];
-
-// Rotary encoder
-const ROTENC_A: u8 = 15;
-const ROTENC_B: u8 = 14;
-
+#[cfg(not(feature = "hardware"))]
+fn open_hardware(_hw: &HardwareConfig) -> io::Result<(Box<dyn InputSource>, Box<dyn LcdDevice>)> {
+    Err(io::Error::new(io::ErrorKind::NotFound, "built without the hardware feature"))
+}
 
-#[derive(Copy, Clone)]
-struct Fade {
-    start_time: DateTime<Local>,
-    end_time: DateTime<Local>,
-    start_vol: f32,
-    end_vol:f32
+fn open_simulator(hw: &HardwareConfig) -> io::Result<(Box<dyn InputSource>, Box<dyn LcdDevice>)> {
+    // the keys press whichever pin the button doing the same is wired to
+    let keys: Vec<(char, u8)> =
+        SIM_KEYS.iter().filter_map(|&(key, action)| hw.get_button_pin(action).map(|pin| (key, pin))).collect();
+    let input = KeyboardInput::new(&keys)?;
+    let lcd = TerminalLcd::new(SIM_LEGEND.to_string());
+    Ok((Box::new(input), Box::new(lcd)))
 }
 
-impl Fade {
-    fn new(start: DateTime<Local>, alarm: &Alarm) -> Fade
-    {
-        Fade{start_time:start,
-             end_time:start+alarm.get_length(),
-             start_vol:alarm.get_start_vol(),
-             end_vol:alarm.get_end_vol()}
-    }
+// Everything that wakes up the main loop
+enum LoopEvent {
+    Input(InputEvent),
+    Web(WebCommand),
+    ConfigReloaded,
+    PlayerChanged,
+    Terminate,
 }
 
-enum PlaybackState {
-    Playing,
-    Paused,
-    Fading(Fade)
+fn input_to_event(x: InputEvent, hw: &HardwareConfig) -> Option<Event> {
+    match x {
+        InputEvent::Button(pin) => match hw.get_button_action(pin) {
+            Some(ButtonAction::Alarm) => {
+                debug!("Alarm button pressed");
+                Some(Event::AlarmButton)
+            }
+            Some(ButtonAction::PlayPause) => {
+                debug!("Toggle play button pressed");
+                Some(Event::PlayButton)
+            }
+            Some(ButtonAction::Light) => {
+                debug!("Toggle backlight button pressed");
+                Some(Event::LightButton)
+            }
+            Some(ButtonAction::Power) => {
+                debug!("Power button pressed");
+                Some(Event::PowerButton)
+            }
+            None => None,
+        },
+        InputEvent::RotaryEncoder(inc) => {
+            match inc.cmp(&0) {
+                Ordering::Greater => debug!("Rotary encoder turned clockwise"),
+                Ordering::Less => debug!("Rotary encoder turned counter-clockwise"),
+                Ordering::Equal => ()
+            }
+            Some(Event::Rotate(inc))
+        }
+    }
 }
 
-struct State {
-    pb_state: PlaybackState,
+fn report_player_error(result: io::Result<()>, command: &str) {
+    if let Err(e) = result {
+        error!("Failed sending {} command to player: {}", command, e);
+    }
 }
 
 fn main()
 {
-    let config_fname = "wump.conf";
-
-    let mut config = Arc::new(RwLock::new(match Config::read_new(config_fname) {
-        Ok(c) => {
-            println!("Reading config from file at {}", config_fname);
-            c
-        },
+    let opts = match cli::Options::parse(std::env::args().skip(1)) {
+        Ok(opts) => opts,
         Err(e) => {
-            println!("No config file found at {}", config_fname);
-            Config::default()
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
         }
-    }));
-    let mut state = State { pb_state: PlaybackState::Paused};
+    };
+    if opts.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if let Some(level) = opts.log_level {
+        logging::set_level(level);
+    }
 
-    let mut input_handler = InputHandler::new(BUTTONS, (ROTENC_A, ROTENC_B));
+    let config_fname = opts.config_path.clone().unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
 
-    let _webui = start_webui(config.clone());
+    // --speed N runs time N times faster, --fast-forward doesn't wait at all
+    let clock: Arc<dyn Clock> = if opts.fast_forward {
+        Arc::new(ManualClock::new(Local::now()))
+    } else if let Some(speed) = opts.speed {
+        Arc::new(AcceleratedClock::new(Local::now(), speed))
+    } else {
+        Arc::new(SystemClock)
+    };
+
+    let config = Arc::new(ConfigStore::load(&config_fname));
+
+    // settings given on the command line take precedence over the config file
+    let (frontend, mpd_address, web_bind, hw) = {
+        let conf = config.read();
+        logging::set_level(opts.log_level.unwrap_or(conf.get_log_level()));
+        let frontend = opts.frontend.unwrap_or(conf.get_frontend());
+        let mpd_address = format!("{}:{}",
+                                  opts.mpd_host.clone().unwrap_or_else(|| conf.mpd_host.clone()),
+                                  opts.mpd_port.unwrap_or(conf.mpd_port));
+        let web_bind = opts.web_bind.clone().unwrap_or_else(|| conf.get_web_bind().to_string());
+        (frontend, mpd_address, web_bind, conf.get_hardware().clone())
+    };
+    let simulate = frontend == Frontend::Simulator;
+
+    let hardware = match frontend {
+        Frontend::Hardware => open_hardware(&hw),
+        Frontend::Simulator => open_simulator(&hw),
+        Frontend::Mock => Err(io::Error::new(io::ErrorKind::NotFound, "disabled in the settings")),
+    };
+    let (mut input_handler, lcd) = match hardware {
+        Ok(hw) => hw,
+        Err(e) => {
+            warn!("No hardware available ({}), using mock input and display", e);
+            let mock_input: Box<dyn InputSource> = Box::new(MockInput::new());
+            let mock_lcd: Box<dyn LcdDevice> = Box::new(MockLcd::new());
+            (mock_input, mock_lcd)
+        }
+    };
+
+    let (events_tx, events_rx) = mpsc::channel();
+
+    let (web_tx, web_rx) = mpsc::channel();
+    // whether anything is playing, for the web UI to tell when a sleep timer makes no sense
+    let playing = Arc::new(AtomicBool::new(false));
+    let _webui = start_webui(config.clone(), web_tx, playing.clone(), clock.clone(), web_bind, mpd_address.clone());
+    let web_events_tx = events_tx.clone();
+    thread::spawn(move || {
+        for cmd in web_rx {
+            let _ = web_events_tx.send(LoopEvent::Web(cmd));
+        }
+    });
+
+    let config_events_tx = events_tx.clone();
+    watch_config(config.clone(), move || {
+        let _ = config_events_tx.send(LoopEvent::ConfigReloaded);
+    });
 
     // Create and initialize display
-    let mut dpy = Display::new().unwrap();
+    let mut dpy = Display::new(lcd).unwrap();
 
     // Send some test
     dpy.set_top_line("Wake-Up MP 0.5").unwrap();
@@ -107,186 +226,156 @@ fn main()
     thread::sleep(Duration::new(1,0));
 
     // clear any initial events:
-    input_handler.handle_events(|x| {
-        println!("Ignoring event {:?}...", x);
+    input_handler.handle_events(&mut |x| {
+        debug!("Ignoring event {:?}...", x);
     });
 
-    let dim_timeout : chrono::Duration = chrono::Duration::seconds(5);
-    let mut last_input_activity = Local::now();
-
-    let mut terminate = Arc::new(AtomicBool::new(false));
-    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&terminate)).unwrap();
-    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&terminate)).unwrap();
-    let mut do_poweroff = false;
-
-    while !terminate.load(SyncOrdering::Relaxed) {
-        let now : DateTime<Local> = Local::now();
-        let mut mpd_conn = mpd::Client::connect("127.0.0.1:6600").expect("Failed connecting to mpd");
-        let mpd_status = mpd_conn.status().expect("Failed querying mpd for status");
-        let mut volume = mpd_status.volume;
-
-        // update state based on external mpd state changes
-        state.pb_state = match mpd_status.state {
-            mpd::State::Stop|mpd::State::Pause => PlaybackState::Paused,
-            mpd::State::Play => match state.pb_state {
-                PlaybackState::Paused => PlaybackState::Playing,
-                PlaybackState::Playing|PlaybackState::Fading(_) => state.pb_state
-            }
-        };
-
-        // gather input events
-        let mut input_toggle_alarm_enabled = false;
-        let mut input_toggle_play = false;
-        let mut input_activity = false;
-        let mut vol_change: i8 = 0;
-
-        input_handler.handle_events(|x| {
-
-            if let InputEvent::Button(BUTTON_A) = x {
-                input_toggle_alarm_enabled = true;
-                println!("Toggle alarm state button pressed");
+    let mut signals = Signals::new([SIGTERM, SIGINT]).unwrap();
+    let signal_events_tx = events_tx.clone();
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            let _ = signal_events_tx.send(LoopEvent::Terminate);
+        }
+    });
 
-            }
-            if let InputEvent::Button(BUTTON_B) = x {
-                input_toggle_play = true;
-                println!("Toggle play button pressed");
-            }
+    let input_events_tx = events_tx.clone();
+    input_handler.start(Box::new(move |x| {
+        let _ = input_events_tx.send(LoopEvent::Input(x));
+    }));
 
-            if let InputEvent::Button(BUTTON_ROT) = x {
-                println!("Rotary encoder button pressed");
-                terminate.store(true,SyncOrdering::Relaxed);
-                do_poweroff = true;
-            }
+    let mut terminate = false;
+    let mut do_poweroff = false;
 
-            if let InputEvent::RotaryEncoder(inc) = x {
-                match inc.cmp(&0) {
-                    Ordering::Greater => println!("Rotary encoder turned clockwise"),
-                    Ordering::Less => println!("Rotary encoder turned counter-clockwise"),
-                    Ordering::Equal => ()
+    // --fake-player runs without MPD, logging what would have been played,
+    // --silent-player also never starts playing, to try the fallback tone
+    let mut player: Box<dyn Player> = if opts.silent_player || opts.fake_player {
+        let mut fake = FakePlayer::new();
+        fake.set_silent(opts.silent_player);
+        Box::new(fake)
+    } else {
+        let player_events_tx = events_tx.clone();
+        watch_mpd(&mpd_address, move || {
+            let _ = player_events_tx.send(LoopEvent::PlayerChanged);
+        });
+        Box::new(ReconnectingPlayer::new(&mpd_address))
+    };
+    let mut player_online = false;
+    // source of an alarm that went off while MPD was down
+    let mut pending_source = None;
+    let mut fallback = FallbackAlarm::new(config.read().get_fallback_command());
+
+    let mut machine = StateMachine::new(&config.read(), clock.now());
+    let mut received = Vec::new();
+
+    while !terminate {
+        let now : DateTime<Local> = clock.now();
+        let status = match player.status() {
+            Ok(status) if !player_online => {
+                player_online = true;
+                // catch up with what happened while the player was unreachable
+                let expected = machine.expected_status();
+                if let Some(alarm) = pending_source.take() {
+                    report_player_error(player.load_source(&alarm), "load");
                 }
-                vol_change += inc;
-            }
-
-            input_activity = true;
-
-            if let InputEvent::Button(BUTTON_C) = x {
-                println!("Toggle backlight button pressed");
-                if dpy.get_backlight() {
-                    input_activity = false;
-                    last_input_activity = now-(dim_timeout+dim_timeout);
+                if expected.playing && !status.playing {
+                    report_player_error(player.set_volume(expected.volume), "set volume");
+                    report_player_error(player.play(), "play");
+                    player.status().ok()
+                } else {
+                    Some(status)
                 }
-                // other case handled by activity = true above
             }
-        });
-
-        // handle input events and alarm state changes
-
-        if input_toggle_alarm_enabled {
-            let mut cfg = config.write().unwrap();
-            cfg.alarm.toggle_enabled();
-            cfg.write(config_fname).unwrap();
-        }
-
-        {
-            let mut cfg = config.write().unwrap();
-
-            if cfg.alarm.should_start(&now) {
-                if let PlaybackState::Paused = state.pb_state {
-                    println!("Starting up the alarm!");
-                    cfg.alarm.start();
-                    state.pb_state=PlaybackState::Fading(Fade::new(now,&cfg.alarm));
-                    cfg.write(config_fname).unwrap();
+            Ok(status) => Some(status),
+            Err(e) => {
+                if player_online {
+                    warn!("Player is offline: {}", e);
+                    player_online = false;
                 }
+                None
             }
-        }
-
-        if input_toggle_play {
-            state.pb_state = match state.pb_state {
-                PlaybackState::Paused => PlaybackState::Playing,
-                PlaybackState::Playing|PlaybackState::Fading(_) => PlaybackState::Paused
-            };
-        }
-
-        // if input=change_volume => { set volume, and if state==fading => state = playing }
-        // volume change
+        };
 
-        if vol_change != 0 {
-            if let PlaybackState::Fading(_) = state.pb_state {
-                state.pb_state = PlaybackState::Playing;
+        let mut events = Vec::new();
+        for event in received.drain(..) {
+            match event {
+                LoopEvent::Input(x) => events.extend(input_to_event(x, &hw)),
+                LoopEvent::Web(cmd) => events.push(Event::Web(cmd)),
+                // the new config and player state are picked up below and above
+                LoopEvent::ConfigReloaded | LoopEvent::PlayerChanged => (),
+                LoopEvent::Terminate => terminate = true,
             }
-            volume = (5*vol_change +volume).min(100).max(0);
-
-            mpd_conn.volume(volume).unwrap();
-
         }
 
-        // handle fading, set volume or change
-
-        if let PlaybackState::Fading(fade) = state.pb_state {
-            println!("start_time: {}, end_time: {}, now: {}", fade.start_time, fade.end_time, now);
-            let num = (now-fade.start_time).num_milliseconds() as f32;
-            let den = (fade.end_time - fade.start_time).num_milliseconds() as f32;
-            let a = (num / den).min(1.0).max(0.0);
-
-            let vol_fraction = fade.start_vol + (fade.end_vol-fade.start_vol)*a;
-            let vol_percent = (vol_fraction*100.0).round() as i8;
-
-            println!("Fading. a={}, setting volume to {}",a, vol_percent);
-            mpd_conn.volume(vol_percent).expect("Failed sending set volume command to mpd.");
-            if now >= fade.end_time {
-                println!("Done fading, switching to PlaybackState::Playing");
-                state.pb_state = PlaybackState::Playing;
+        // a failure to save is logged by the store, the alarms keep running regardless
+        let (actions, _) = config.update(|cfg| machine.update(cfg, status, &events, now));
+        playing.store(machine.expected_status().playing, AtomicOrdering::Relaxed);
+
+        for action in actions {
+            match action {
+                Action::LoadSource(alarm) => {
+                    if !player_online {
+                        pending_source = Some(alarm);
+                    } else if let Err(e) = player.load_source(&alarm) {
+                        error!("Failed loading {:?} for alarm, playing current queue: {}", alarm.get_source(), e);
+                    }
+                }
+                // nothing to send while offline, playback is resumed on reconnect
+                Action::SetVolume(_)|Action::Play|Action::Pause if !player_online => (),
+                Action::SetVolume(vol) => report_player_error(player.set_volume(vol), "set volume"),
+                Action::Play => report_player_error(player.play(), "play"),
+                Action::Pause => report_player_error(player.pause(), "pause"),
+                Action::SetBacklight(on) => dpy.set_backlight(on).unwrap(),
+                Action::SetFallback(on) => fallback.set_active(on),
+                Action::PowerOff => {
+                    terminate = true;
+                    do_poweroff = true;
+                }
             }
         }
 
-        // handle playback state changes (due to button press or alarm starting)
-        match mpd_status.state {
-            mpd::State::Stop|mpd::State::Pause => match state.pb_state {
-                PlaybackState::Playing|PlaybackState::Fading(_) => mpd_conn.play().expect("Failed sending play command to mpd."),
-                _ => ()
-            },
-            mpd::State::Play => if let PlaybackState::Paused = state.pb_state {
-                mpd_conn.pause(true).expect("Failed sending pause command to mpd.")
-            }
-        };
-
-        // handle backlight toggle
-        if input_activity {
-            last_input_activity = now;
-            dpy.set_backlight(true).unwrap();
-        } else {
-            let dur = now.signed_duration_since(last_input_activity);
-            if dur > dim_timeout {
-                dpy.set_backlight(false).unwrap();
-            }
+        if !machine.is_alarm_active() {
+            pending_source = None;
         }
 
-        let pbstring = if let PlaybackState::Paused = state.pb_state { "Paused" } else { "Playing" };
-        let l1 = format!("Vol: {}    {}", volume, pbstring);
-        let alarm_str = config.read().unwrap().alarm.to_str();
-        let l2 = format!("A: {}", alarm_str);
+        let l1 = if player_online { machine.top_line(&now) } else { "MPD offline".to_string() };
+        let l2 = machine.bottom_line(&config.read(), &now);
 
         dpy.show_time(&now).unwrap();
         dpy.set_top_line(&l1).unwrap();
         dpy.set_bottom_line(&l2).unwrap();
 
-        thread::sleep(Duration::from_millis(250));
+        // sleep until something happens or there is something to do
+        let mut deadline = machine.next_deadline(&config.read(), &now);
+        if !player_online {
+            deadline = deadline.min(now + chrono::Duration::milliseconds(PLAYER_RETRY_MS));
+        }
+        let timeout = (deadline - clock.now()).to_std().unwrap_or(Duration::from_millis(0));
+        match events_rx.recv_timeout(clock.real_timeout(timeout)) {
+            Ok(event) => {
+                received.push(event);
+                received.extend(events_rx.try_iter());
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
     }
 
     dpy.clear().unwrap();
 
-    if do_poweroff {
+    if do_poweroff && simulate {
+        info!("Power off requested, exiting the simulator");
+    } else if do_poweroff {
         dpy.set_top_line("Shutting down...").unwrap();
         let output = std::process::Command::new("sudo").arg("poweroff").output().unwrap();
-        println!("Poweroff returned with status: {}", output.status);
-        println!("output: {}", std::str::from_utf8(&output.stdout).unwrap());
+        info!("Poweroff returned with status: {}", output.status);
+        info!("output: {}", std::str::from_utf8(&output.stdout).unwrap());
         dpy.set_bottom_line("poweroff returned.").unwrap();
         loop {
-            println!("sleeping...");
+            debug!("sleeping...");
             thread::sleep(Duration::new(1,0));
         }
     } else {
-        println!("Exiting...");
+        info!("Exiting...");
         dpy.set_top_line("Wump exiting...").unwrap();
         thread::sleep(Duration::new(1,0));
     }
@@ -0,0 +1,70 @@
+extern crate serde_json;
+
+use self::serde_json::{Map, Value};
+use std::io;
+
+/// Version of the config file format written by this build.
+pub const CONFIG_VERSION: u64 = 2;
+
+// Turns a config into the next version, and describes what it did
+type Migration = fn(&mut Map<String, Value>) -> String;
+
+// The migration at each index starts from the version equal to that index
+const MIGRATIONS: &[Migration] = &[migrate_v0, migrate_v1];
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Files written before the version field was added are told apart by whether
+// they hold a single `alarm` or a list of `alarms`.
+fn detect_version(conf: &Map<String, Value>) -> io::Result<u64> {
+    match conf.get("version") {
+        Some(v) => v.as_u64().ok_or_else(|| invalid_data(format!("invalid config version {}", v))),
+        None if conf.contains_key("alarm") && !conf.contains_key("alarms") => Ok(0),
+        None => Ok(1),
+    }
+}
+
+// Version 0 supported only one alarm.
+fn migrate_v0(conf: &mut Map<String, Value>) -> String {
+    let mut alarm = conf.remove("alarm").unwrap_or(Value::Null);
+    if let Value::Object(ref mut fields) = alarm {
+        let unnamed = fields.get("name").and_then(Value::as_str).is_none_or(str::is_empty);
+        if unnamed {
+            fields.insert("name".to_string(), Value::from("Alarm"));
+        }
+    }
+    conf.insert("alarms".to_string(), Value::Array(vec![alarm]));
+    "moved the single alarm into the list of alarms".to_string()
+}
+
+// Version 1 had no version field.
+fn migrate_v1(_conf: &mut Map<String, Value>) -> String {
+    "added the version field".to_string()
+}
+
+/// Brings a config file read as JSON up to `CONFIG_VERSION`. Files written
+/// by a newer version are rejected rather than have settings dropped.
+pub fn migrate(value: Value) -> io::Result<Value> {
+    let mut conf = match value {
+        Value::Object(conf) => conf,
+        _ => return Err(invalid_data("the config is not a JSON object".to_string())),
+    };
+
+    let version = detect_version(&conf)?;
+    if version > CONFIG_VERSION {
+        return Err(invalid_data(format!(
+            "the config has version {}, but only versions up to {} are supported",
+            version, CONFIG_VERSION
+        )));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let change = migration(&mut conf);
+        info!("Migrated the config from version {} to {}: {}", from, from + 1, change);
+    }
+    conf.insert("version".to_string(), Value::from(CONFIG_VERSION));
+
+    Ok(Value::Object(conf))
+}
@@ -0,0 +1,711 @@
+extern crate chrono;
+
+use self::chrono::{DateTime, Duration, Local, Timelike};
+
+use alarm::Alarm;
+use config::Config;
+use fade::{Fade, FadeCurve};
+use webui::WebCommand;
+
+// Time without input after which the backlight is turned off
+const DIM_TIMEOUT_MS: i64 = 5000;
+
+// A double press on the light button lets the rotary encoder set the sleep
+// timer instead of the volume for a while.
+const DOUBLE_PRESS_MS: i64 = 1000;
+const SLEEP_ADJUST_TIMEOUT_MS: i64 = 5000;
+const SLEEP_ADJUST_STEP_MIN: i64 = 5;
+// How long the alarm button keeps acting on the same alarm after a press
+const ALARM_CYCLE_TIMEOUT_MS: i64 = 5000;
+
+// Volume change per step of the rotary encoder
+const VOLUME_STEP: i8 = 5;
+
+// Time between volume updates while fading
+const FADE_STEP_MS: i64 = 250;
+
+/// What the player reported at the start of a tick.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub playing: bool,
+    pub volume:  i8,
+}
+
+/// User input, independent of how it was entered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    AlarmButton,
+    PlayButton,
+    LightButton,
+    PowerButton,
+    Rotate(i8),
+    Web(WebCommand),
+}
+
+/// Side effects requested by the state machine, to be carried out in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    LoadSource(Alarm),
+    SetVolume(i8),
+    Play,
+    Pause,
+    SetBacklight(bool),
+    SetFallback(bool),
+    PowerOff,
+}
+
+#[derive(Copy, Clone)]
+enum PlaybackState {
+    Playing,
+    Paused,
+    Fading(Fade),
+    FadingOut(Fade),
+    Snoozed(DateTime<Local>),
+}
+
+// The alarm that most recently went off, kept until playback is paused so
+// that it can be snoozed and restarted.
+struct Ringing {
+    alarm:     Alarm,
+    snoozes:   u32,
+    // when playback is stopped automatically, unless the user intervenes
+    stop_at:   Option<DateTime<Local>>,
+    // when playback was last asked to start, and whether the player has
+    // been seen playing since
+    started:   DateTime<Local>,
+    confirmed: bool,
+    // whether the fallback tone is needed since the player isn't playing
+    fallback:  bool,
+}
+
+impl Ringing {
+    fn new(alarm: &Alarm, now: DateTime<Local>) -> Ringing {
+        Ringing {
+            alarm:     alarm.clone(),
+            snoozes:   0,
+            stop_at:   alarm.get_auto_stop().map(|d| now + d),
+            started:   now,
+            confirmed: false,
+            fallback:  false,
+        }
+    }
+
+    // Starts over after the alarm went off or a snooze ended.
+    fn restart(&mut self, now: DateTime<Local>) {
+        self.stop_at = self.alarm.get_auto_stop().map(|d| now + d);
+        self.started = now;
+        self.confirmed = false;
+        self.fallback = false;
+    }
+}
+
+/// The playback, alarm and backlight logic of the player, free of any I/O.
+/// Each call to `update` advances it by one tick.
+pub struct StateMachine {
+    pb_state:            PlaybackState,
+    ringing:             Option<Ringing>,
+    sleep_at:            Option<DateTime<Local>>,
+    volume:              i8,
+    backlight_on:        bool,
+    fallback_on:         bool,
+    last_tick:           DateTime<Local>,
+    last_input_activity: DateTime<Local>,
+    last_light_press:    Option<DateTime<Local>>,
+    sleep_adjust_until:  DateTime<Local>,
+    // the alarm the alarm button is stepping through and until when, so that
+    // further presses act on it even once another alarm has become the next
+    cycled_alarm:        Option<(usize, DateTime<Local>)>,
+}
+
+impl StateMachine {
+    pub fn new(config: &Config, now: DateTime<Local>) -> StateMachine {
+        StateMachine {
+            pb_state:            PlaybackState::Paused,
+            ringing:             None,
+            sleep_at:            None,
+            volume:              0,
+            backlight_on:        false,
+            fallback_on:         false,
+            // alarms that went off while the daemon was down are still
+            // started if they are within the grace period
+            last_tick:           now - config.get_trigger_grace(),
+            last_input_activity: now,
+            last_light_press:    None,
+            sleep_adjust_until:  now,
+            cycled_alarm:        None,
+        }
+    }
+
+    /// The player status matching the current state, to use while the
+    /// player can't be asked.
+    pub fn expected_status(&self) -> PlayerStatus {
+        let playing = match self.pb_state {
+            PlaybackState::Playing|PlaybackState::Fading(_)|PlaybackState::FadingOut(_) => true,
+            PlaybackState::Paused|PlaybackState::Snoozed(_) => false
+        };
+        PlayerStatus { playing, volume: self.volume }
+    }
+
+    /// Whether an alarm went off and hasn't been stopped yet. It may be
+    /// snoozed.
+    pub fn is_alarm_active(&self) -> bool {
+        self.ringing.is_some()
+    }
+
+    /// Whether an alarm is going off right now, as opposed to snoozed or
+    /// stopped.
+    pub fn is_alarm_sounding(&self) -> bool {
+        self.expected_status().playing && self.is_alarm_active()
+    }
+
+    fn set_volume(&mut self, actions: &mut Vec<Action>, volume: i8) {
+        self.volume = volume;
+        actions.push(Action::SetVolume(volume));
+    }
+
+    /// Advances the state to `now`. `status` is what the player reported,
+    /// or `None` if it couldn't be reached.
+    pub fn update(&mut self, config: &mut Config, status: Option<PlayerStatus>, events: &[Event],
+                  now: DateTime<Local>) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let reported = status;
+        let status = status.unwrap_or_else(|| self.expected_status());
+        self.volume = status.volume;
+
+        let starting_alarm = self.ringing.as_ref().is_some_and(|r| !r.confirmed);
+
+        // update state based on external player state changes
+        self.pb_state = match reported {
+            None => self.pb_state,
+            Some(PlayerStatus { playing: true, .. }) => match self.pb_state {
+                PlaybackState::Paused|PlaybackState::Snoozed(_) => PlaybackState::Playing,
+                PlaybackState::Playing|PlaybackState::Fading(_)|PlaybackState::FadingOut(_) => self.pb_state
+            },
+            Some(_) => match self.pb_state {
+                PlaybackState::Snoozed(_) => self.pb_state,
+                // an alarm that didn't start playing yet wasn't paused by
+                // anyone, keep asking the player to play it
+                PlaybackState::Playing|PlaybackState::Fading(_)|PlaybackState::FadingOut(_) if starting_alarm => {
+                    self.pb_state
+                }
+                _ => PlaybackState::Paused
+            }
+        };
+
+        if let Some(ringing) = self.ringing.as_mut() {
+            let overdue = now - ringing.started >= config.get_fallback_delay();
+            match reported {
+                Some(PlayerStatus { playing: true, .. }) => {
+                    ringing.confirmed = true;
+                    ringing.fallback = false;
+                }
+                None if ringing.confirmed => ringing.fallback = true,
+                _ if !ringing.confirmed && overdue && !ringing.fallback => {
+                    warn!("Alarm '{}' didn't start playing, sounding the fallback tone", ringing.alarm.get_name());
+                    ringing.fallback = true;
+                }
+                _ => ()
+            }
+        }
+
+        // gather input events
+        let dim_timeout = Duration::milliseconds(DIM_TIMEOUT_MS);
+        let mut input_alarm_button = false;
+        let mut input_toggle_play = false;
+        let mut input_activity = false;
+        let mut input_sleep_gesture = false;
+        let mut vol_change: i8 = 0;
+        let mut web_commands = Vec::new();
+
+        for event in events {
+            // commands from the web UI don't light up the display
+            if let Event::Web(ref cmd) = *event {
+                web_commands.push(cmd.clone());
+                continue;
+            }
+
+            input_activity = true;
+
+            match *event {
+                Event::AlarmButton => input_alarm_button = true,
+                Event::PlayButton => input_toggle_play = true,
+                Event::PowerButton => actions.push(Action::PowerOff),
+                Event::Rotate(inc) => vol_change += inc,
+                Event::LightButton => {
+                    if self.last_light_press.is_some_and(|t| now - t < Duration::milliseconds(DOUBLE_PRESS_MS)) {
+                        info!("Sleep timer gesture detected");
+                        input_sleep_gesture = true;
+                    }
+                    self.last_light_press = Some(now);
+                    if self.backlight_on {
+                        input_activity = false;
+                        self.last_input_activity = now-(dim_timeout+dim_timeout);
+                    }
+                    // other case handled by activity = true above
+                }
+                Event::Web(_) => ()
+            }
+        }
+
+        // handle input events and alarm state changes
+
+        for cmd in web_commands {
+            info!("Received command from web UI: {:?}", cmd);
+            match cmd {
+                WebCommand::StartSleepTimer(_) if !self.expected_status().playing => {
+                    info!("Nothing is playing, ignoring the sleep timer");
+                }
+                WebCommand::StartSleepTimer(minutes) => {
+                    match Duration::try_minutes(minutes).and_then(|d| now.checked_add_signed(d)) {
+                        Some(sleep_at) => self.sleep_at = Some(sleep_at),
+                        None => warn!("Ignoring a sleep timer of {} minutes, which is too long", minutes),
+                    }
+                }
+                WebCommand::CancelSleepTimer => {
+                    self.sleep_at = None;
+                    if let PlaybackState::FadingOut(fade) = self.pb_state {
+                        if self.ringing.is_none() {
+                            self.pb_state = PlaybackState::Playing;
+                            self.set_volume(&mut actions, (fade.get_start_vol()*100.0).round() as i8);
+                        }
+                    }
+                }
+                // the alarms are looked at on every update anyway
+                WebCommand::ConfigChanged => ()
+            }
+        }
+
+        if input_sleep_gesture {
+            // keep the display lit while setting the timer
+            input_activity = true;
+            self.sleep_adjust_until = now + Duration::milliseconds(SLEEP_ADJUST_TIMEOUT_MS);
+            if self.sleep_at.is_none() {
+                self.sleep_at = Some(now + config.get_sleep_timer());
+            }
+        }
+
+        if vol_change != 0 && now < self.sleep_adjust_until {
+            self.sleep_adjust_until = now + Duration::milliseconds(SLEEP_ADJUST_TIMEOUT_MS);
+            if let Some(sleep_at) = self.sleep_at {
+                let new_sleep_at = sleep_at + Duration::minutes(SLEEP_ADJUST_STEP_MIN * vol_change as i64);
+                self.sleep_at = if new_sleep_at > now { Some(new_sleep_at) } else { None };
+                info!("Sleep timer set to {:?}", self.sleep_at);
+            }
+            vol_change = 0;
+        }
+
+        if input_alarm_button && self.is_alarm_sounding() {
+            // the alarm button snoozes a ringing alarm instead of toggling it
+            let ringing = self.ringing.as_mut().unwrap();
+            if ringing.snoozes < ringing.alarm.get_max_snoozes() {
+                ringing.snoozes += 1;
+                let until = now + ringing.alarm.get_snooze_length();
+                info!("Snoozing alarm '{}' until {} ({} of {})", ringing.alarm.get_name(), until,
+                         ringing.snoozes, ringing.alarm.get_max_snoozes());
+                self.pb_state = PlaybackState::Snoozed(until);
+            } else {
+                info!("Maximum number of snoozes reached for alarm '{}'", ringing.alarm.get_name());
+            }
+        } else if input_alarm_button {
+            if let Some(i) = self.displayed_alarm(config, &now) {
+                config.alarms[i].cycle_enabled();
+                self.cycled_alarm = Some((i, now + Duration::milliseconds(ALARM_CYCLE_TIMEOUT_MS)));
+            }
+        }
+
+        let grace = config.get_trigger_grace();
+
+        for alarm in config.alarms.iter_mut() {
+            let occurrence = match alarm.due_occurrence(&self.last_tick, &now, grace) {
+                Some(occurrence) => occurrence,
+                None => continue
+            };
+
+            if !alarm.start(&occurrence) {
+                info!("Skipping the {} occurrence of alarm '{}'", occurrence, alarm.get_name());
+            } else if let PlaybackState::Paused = self.pb_state {
+                info!("Starting up alarm '{}' due at {}!", alarm.get_name(), occurrence);
+                actions.push(Action::LoadSource(alarm.clone()));
+                self.pb_state = PlaybackState::Fading(Fade::new(now, alarm));
+                self.ringing = Some(Ringing::new(alarm, now));
+            } else {
+                info!("Alarm '{}' due at {} went off during playback", alarm.get_name(), occurrence);
+            }
+        }
+        self.last_tick = now;
+
+        config.disable_expired(&(now - grace));
+
+        if let PlaybackState::Snoozed(until) = self.pb_state {
+            if now >= until {
+                info!("Snooze is over, restarting the alarm");
+                let ringing = self.ringing.as_mut().unwrap();
+                ringing.restart(now);
+                self.pb_state = PlaybackState::Fading(Fade::new(now, &ringing.alarm));
+            }
+        }
+
+        if let Some(ringing) = self.ringing.as_ref() {
+            let fade_length = ringing.alarm.get_auto_stop_fade();
+            let fade_out_due = ringing.stop_at.is_some_and(|stop_at| now >= stop_at - fade_length);
+            match self.pb_state {
+                PlaybackState::Playing|PlaybackState::Fading(_) if fade_out_due => {
+                    info!("Alarm '{}' has played long enough, fading out", ringing.alarm.get_name());
+                    let from_vol = self.volume as f32 / 100.0;
+                    let fade = Fade::fade_out(now, fade_length, from_vol, ringing.alarm.get_fade_curve());
+                    self.pb_state = PlaybackState::FadingOut(fade);
+                }
+                _ => ()
+            }
+        }
+
+        if let Some(sleep_at) = self.sleep_at {
+            match self.pb_state {
+                PlaybackState::Playing|PlaybackState::Fading(_) if now >= sleep_at - config.get_sleep_fade() => {
+                    info!("Sleep timer is running out, fading out");
+                    let fade = Fade::fade_out(now, sleep_at - now, self.volume as f32 / 100.0, FadeCurve::Exponential);
+                    self.pb_state = PlaybackState::FadingOut(fade);
+                }
+                _ => ()
+            }
+        }
+
+        // volume to go back to after a fade out, once playback is paused
+        let mut restore_volume = None;
+
+        if input_toggle_play {
+            self.pb_state = match self.pb_state {
+                PlaybackState::Paused => PlaybackState::Playing,
+                PlaybackState::FadingOut(fade) => {
+                    restore_volume = Some((fade.get_start_vol()*100.0).round() as i8);
+                    PlaybackState::Paused
+                }
+                PlaybackState::Playing|PlaybackState::Fading(_)|PlaybackState::Snoozed(_) => PlaybackState::Paused
+            };
+        }
+
+        if let PlaybackState::Paused = self.pb_state {
+            self.ringing = None;
+            self.sleep_at = None;
+        }
+
+        // volume change
+
+        if vol_change != 0 {
+            match self.pb_state {
+                PlaybackState::Fading(_) => self.pb_state = PlaybackState::Playing,
+                PlaybackState::FadingOut(_) => {
+                    self.pb_state = PlaybackState::Playing;
+                    self.sleep_at = None;
+                }
+                _ => ()
+            }
+            // somebody is around, so don't stop the alarm automatically
+            if let Some(ringing) = self.ringing.as_mut() {
+                ringing.stop_at = None;
+            }
+            let volume = (VOLUME_STEP*vol_change + self.volume).clamp(0, 100);
+            self.set_volume(&mut actions, volume);
+        }
+
+        // handle fading, set volume or change
+
+        if let PlaybackState::Fading(fade) = self.pb_state {
+            let vol_percent = fade.volume_at(&now);
+
+            debug!("Fading. a={}, setting volume to {}", fade.progress(&now), vol_percent);
+            self.set_volume(&mut actions, vol_percent);
+            if fade.is_done(&now) {
+                debug!("Done fading, switching to PlaybackState::Playing");
+                self.pb_state = PlaybackState::Playing;
+            }
+        }
+
+        if let PlaybackState::FadingOut(fade) = self.pb_state {
+            let vol_percent = fade.volume_at(&now);
+
+            debug!("Fading out. a={}, setting volume to {}", fade.progress(&now), vol_percent);
+            self.set_volume(&mut actions, vol_percent);
+            if fade.is_done(&now) {
+                debug!("Done fading out, switching to PlaybackState::Paused");
+                self.pb_state = PlaybackState::Paused;
+                self.ringing = None;
+                self.sleep_at = None;
+                restore_volume = Some((fade.get_start_vol()*100.0).round() as i8);
+            }
+        }
+
+        // handle playback state changes (due to button press or alarm starting)
+        match self.pb_state {
+            PlaybackState::Playing|PlaybackState::Fading(_)|PlaybackState::FadingOut(_) if !status.playing => {
+                actions.push(Action::Play)
+            }
+            PlaybackState::Paused|PlaybackState::Snoozed(_) if status.playing => actions.push(Action::Pause),
+            _ => ()
+        }
+
+        if let Some(volume) = restore_volume {
+            self.set_volume(&mut actions, volume);
+        }
+
+        // handle backlight toggle
+        let backlight_on = input_activity || now.signed_duration_since(self.last_input_activity) <= dim_timeout;
+        if input_activity {
+            self.last_input_activity = now;
+        }
+        if backlight_on != self.backlight_on {
+            self.backlight_on = backlight_on;
+            actions.push(Action::SetBacklight(backlight_on));
+        }
+
+        let fallback_on = self.is_alarm_sounding() && self.ringing.as_ref().is_some_and(|r| r.fallback);
+        if fallback_on != self.fallback_on {
+            self.fallback_on = fallback_on;
+            actions.push(Action::SetFallback(fallback_on));
+        }
+
+        actions
+    }
+
+    /// Index of the alarm shown on the display and toggled by the alarm
+    /// button: the one the button was last pressed for if that was recently,
+    /// otherwise the one chosen by the config.
+    fn displayed_alarm(&self, config: &Config, now: &DateTime<Local>) -> Option<usize> {
+        match self.cycled_alarm {
+            Some((i, cycle_until)) if *now < cycle_until && i < config.alarms.len() => Some(i),
+            _ => config.displayed_alarm(now),
+        }
+    }
+
+    /// The latest time at which `update` has to be called again, assuming
+    /// nothing else happens until then.
+    pub fn next_deadline(&self, config: &Config, now: &DateTime<Local>) -> DateTime<Local> {
+        // the clock on the display changes every minute
+        let to_next_minute =
+            Duration::seconds(60 - now.second() as i64) - Duration::nanoseconds(now.nanosecond() as i64);
+        let mut deadline = *now + to_next_minute;
+
+        let mut until = |t: DateTime<Local>| {
+            if t > *now && t < deadline {
+                deadline = t;
+            }
+        };
+
+        match self.pb_state {
+            PlaybackState::Fading(_)|PlaybackState::FadingOut(_) => until(*now + Duration::milliseconds(FADE_STEP_MS)),
+            PlaybackState::Snoozed(snooze_end) => until(snooze_end),
+            PlaybackState::Playing|PlaybackState::Paused => ()
+        }
+
+        if self.backlight_on {
+            until(self.last_input_activity + Duration::milliseconds(DIM_TIMEOUT_MS + 1));
+        }
+        until(self.sleep_adjust_until);
+        if let Some((_, cycle_until)) = self.cycled_alarm {
+            until(cycle_until);
+        }
+
+        if let Some(ringing) = self.ringing.as_ref() {
+            if let Some(stop_at) = ringing.stop_at {
+                until(stop_at - ringing.alarm.get_auto_stop_fade());
+            }
+            if !ringing.confirmed {
+                until(ringing.started + config.get_fallback_delay());
+            }
+        }
+
+        if let Some(sleep_at) = self.sleep_at {
+            until(sleep_at - config.get_sleep_fade());
+            // the remaining minutes are shown on the display
+            until(sleep_at - Duration::minutes((sleep_at - *now).num_minutes()));
+        }
+
+        if let Some(next) = config.next_alarm(now).and_then(|i| config.alarms[i].next_occurrence(now)) {
+            until(next);
+        }
+
+        deadline
+    }
+
+    /// Text for the top line of the display.
+    pub fn top_line(&self, now: &DateTime<Local>) -> String {
+        let pbstring = match self.pb_state {
+            PlaybackState::Paused => "Paused",
+            PlaybackState::Snoozed(_) => "Snoozed",
+            _ => "Playing"
+        };
+        match self.sleep_at {
+            Some(sleep_at) => {
+                let minutes = (sleep_at - *now).num_minutes() + 1;
+                let remaining = if minutes < 100 { format!("{}m", minutes) } else { format!("{}h", minutes / 60) };
+                format!("Vol: {:<3} Sleep {}", self.volume, remaining)
+            }
+            None => format!("Vol: {}    {}", self.volume, pbstring)
+        }
+    }
+
+    /// Text for the bottom line of the display.
+    pub fn bottom_line(&self, config: &Config, now: &DateTime<Local>) -> String {
+        if let PlaybackState::Snoozed(until) = self.pb_state {
+            return format!("Snoozed till {}", until.format("%H:%M"));
+        }
+        let alarm_str = match self.displayed_alarm(config, now) {
+            Some(i) => config.alarms[i].to_str(),
+            None => "None".to_string()
+        };
+        format!("A: {}", alarm_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::{Clock, ManualClock};
+    use player::{FakePlayer, Player, PlayerCommand};
+    use std::time::Instant;
+    use alarm::{AlarmMode, DayMask, Time};
+    use test_util::{alarm_at_seven, at, on, one_time_config, run};
+
+    const PAUSED: PlayerStatus = PlayerStatus { playing: false, volume: 50 };
+
+    fn playing(volume: i8) -> PlayerStatus {
+        PlayerStatus { playing: true, volume }
+    }
+
+    // Runs one tick, leaving out the backlight which every input turns on
+    fn tick(machine: &mut StateMachine, config: &mut Config, status: PlayerStatus, events: &[Event],
+            now: DateTime<Local>) -> Vec<Action> {
+        let actions = machine.update(config, Some(status), events, now);
+        actions.into_iter().filter(|a| !matches!(a, Action::SetBacklight(_))).collect()
+    }
+
+    // Lets the alarm go off at 07:00 and fade in until 07:01
+    fn start_alarm(config: &mut Config) -> StateMachine {
+        let mut machine = StateMachine::new(config, at(6, 59, 0));
+        tick(&mut machine, config, PAUSED, &[], at(6, 59, 0));
+        tick(&mut machine, config, PAUSED, &[], at(7, 0, 0));
+        tick(&mut machine, config, playing(10), &[], at(7, 1, 0));
+        machine
+    }
+
+    #[test]
+    fn due_alarm_loads_its_source_and_fades_in() {
+        let mut config = one_time_config();
+        let mut machine = StateMachine::new(&config, at(6, 59, 0));
+        assert!(tick(&mut machine, &mut config, PAUSED, &[], at(6, 59, 0)).is_empty());
+
+        let actions = tick(&mut machine, &mut config, PAUSED, &[], at(7, 0, 0));
+        assert!(matches!(actions[0], Action::LoadSource(_)));
+        assert_eq!(actions[1..], [Action::SetVolume(10), Action::Play]);
+        assert!(machine.is_alarm_sounding());
+
+        let mut volumes = vec![10];
+        for sec in (15..=60).step_by(15) {
+            let now = at(7, 0, 0) + Duration::seconds(sec);
+            for action in tick(&mut machine, &mut config, playing(*volumes.last().unwrap()), &[], now) {
+                match action {
+                    Action::SetVolume(volume) => volumes.push(volume),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+        }
+        assert!(volumes.windows(2).all(|w| w[1] > w[0]), "{:?}", volumes);
+        assert_eq!(volumes.last(), Some(&70));
+    }
+
+    #[test]
+    fn snoozed_alarm_restarts_after_the_snooze() {
+        let mut config = one_time_config();
+        let mut machine = start_alarm(&mut config);
+
+        let actions = tick(&mut machine, &mut config, playing(70), &[Event::AlarmButton], at(7, 2, 0));
+        assert_eq!(actions, [Action::Pause]);
+        assert!(machine.is_alarm_active());
+        assert!(!machine.is_alarm_sounding());
+
+        // the default snooze is nine minutes
+        assert!(tick(&mut machine, &mut config, PAUSED, &[], at(7, 10, 59)).is_empty());
+        let actions = tick(&mut machine, &mut config, PAUSED, &[], at(7, 11, 0));
+        assert_eq!(actions, [Action::SetVolume(10), Action::Play]);
+        assert!(machine.is_alarm_sounding());
+    }
+
+    #[test]
+    fn play_button_stops_the_alarm() {
+        let mut config = one_time_config();
+        let mut machine = start_alarm(&mut config);
+
+        let actions = tick(&mut machine, &mut config, playing(70), &[Event::PlayButton], at(7, 2, 0));
+        assert_eq!(actions, [Action::Pause]);
+        assert!(!machine.is_alarm_active());
+
+        // the alarm button toggles the alarms again rather than snoozing
+        tick(&mut machine, &mut config, PAUSED, &[Event::AlarmButton], at(7, 3, 0));
+        assert!(!machine.is_alarm_active());
+    }
+
+    #[test]
+    fn alarm_fades_out_when_it_has_played_long_enough() {
+        let mut config = one_time_config();
+        config.alarms[0].set_auto_stop(Some(10 * 60), 60);
+        let mut machine = start_alarm(&mut config);
+
+        assert!(tick(&mut machine, &mut config, playing(70), &[], at(7, 8, 59)).is_empty());
+        let actions = tick(&mut machine, &mut config, playing(70), &[], at(7, 9, 0));
+        assert_eq!(actions, [Action::SetVolume(70)]);
+
+        let actions = tick(&mut machine, &mut config, playing(70), &[], at(7, 9, 30));
+        match actions[..] {
+            [Action::SetVolume(volume)] => assert!(volume > 0 && volume < 70, "{}", volume),
+            _ => panic!("unexpected {:?}", actions),
+        }
+
+        // paused at the end, with the volume back up for next time
+        let actions = tick(&mut machine, &mut config, playing(10), &[], at(7, 10, 0));
+        assert_eq!(actions, [Action::SetVolume(0), Action::Pause, Action::SetVolume(70)]);
+        assert!(!machine.is_alarm_active());
+    }
+    #[test]
+    fn alarm_button_keeps_cycling_the_same_alarm() {
+        let weekdays = AlarmMode::Recurring(DayMask::default());
+        let eight = Alarm::new("Later".to_string(), true, Time::new(8, 0).unwrap(), 60, 0.1, 0.7, weekdays);
+        let mut config = Config { alarms: vec![alarm_at_seven(weekdays), eight], ..Config::default() };
+        let mut machine = StateMachine::new(&config, at(6, 0, 0));
+        let press = |machine: &mut StateMachine, config: &mut Config, now| {
+            tick(machine, config, PAUSED, &[Event::AlarmButton], now);
+            machine.bottom_line(config, &now)
+        };
+
+        // skipping the 07:00 alarm makes the 08:00 one the next, but the
+        // button stays with the 07:00 one through the whole cycle
+        assert_eq!(press(&mut machine, &mut config, at(6, 0, 0)), "A: 07:00 (skip)");
+        assert_eq!(press(&mut machine, &mut config, at(6, 0, 1)), "A: Disabled");
+        assert_eq!(press(&mut machine, &mut config, at(6, 0, 2)), "A: 07:00 (M-F)");
+        assert!(config.alarms[0].is_enabled() && !config.alarms[0].is_skipping_next());
+        assert!(config.alarms[1].is_enabled() && !config.alarms[1].is_skipping_next());
+
+        // until the button hasn't been used for a while
+        press(&mut machine, &mut config, at(6, 1, 0));
+        assert_eq!(machine.bottom_line(&config, &at(6, 1, 4)), "A: 07:00 (skip)");
+        assert_eq!(machine.bottom_line(&config, &at(6, 1, 5)), "A: 08:00 (M-F)");
+    }
+
+    #[test]
+    fn weekday_alarm_goes_off_each_weekday_of_a_week() {
+        let monday = on(8, 0, 0, 0);
+        let mut config = Config::default();
+        config.alarms[0].set_auto_stop(Some(10 * 60), 30);
+        let mut player = FakePlayer::new();
+        let clock = ManualClock::new(monday);
+        let mut machine = StateMachine::new(&config, monday);
+
+        let real_start = Instant::now();
+        run(&mut machine, &mut config, &mut player, &clock, monday + Duration::weeks(1));
+        assert!(real_start.elapsed() < ::std::time::Duration::from_secs(5), "took {:?}", real_start.elapsed());
+
+        let starts = player.get_commands().iter().filter(|c| matches!(c, PlayerCommand::LoadSource(_))).count();
+        assert_eq!(starts, 5);
+        assert!(!player.status().unwrap().playing);
+        assert!(clock.now() > monday + Duration::weeks(1));
+    }
+}
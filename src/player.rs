@@ -0,0 +1,380 @@
+extern crate mpd;
+
+use self::mpd::idle::{Idle, Subsystem};
+use std::cmp::min;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alarm::{Alarm, AlarmSource};
+use playback::PlayerStatus;
+
+/// The music player operations the daemon needs.
+pub trait Player {
+    fn status(&mut self) -> io::Result<PlayerStatus>;
+
+    fn set_volume(&mut self, volume: i8) -> io::Result<()>;
+
+    fn play(&mut self) -> io::Result<()>;
+
+    fn pause(&mut self) -> io::Result<()>;
+
+    /// Sets up the queue and playback options for `alarm` going off.
+    fn load_source(&mut self, alarm: &Alarm) -> io::Result<()>;
+}
+
+pub fn mpd_error(e: mpd::error::Error) -> io::Error {
+    match e {
+        mpd::error::Error::Io(e) => e,
+        e => io::Error::other(format!("MPD error: {}", e)),
+    }
+}
+
+// How long to wait for MPD to accept a connection or answer a command
+const MPD_TIMEOUT_MS: u64 = 3000;
+
+/// Connects to MPD at `address`, giving up on an MPD that doesn't answer
+/// within a few seconds. Also returns a handle on the socket, through which
+/// the timeouts can be changed later.
+pub fn connect_mpd(address: &str) -> io::Result<(mpd::Client, TcpStream)> {
+    let timeout = Duration::from_millis(MPD_TIMEOUT_MS);
+    let mut last_error = io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", address));
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                let handle = stream.try_clone()?;
+                let conn = mpd::Client::new(stream).map_err(mpd_error)?;
+                return Ok((conn, handle));
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+pub struct MpdPlayer {
+    conn: mpd::Client,
+}
+
+impl MpdPlayer {
+    pub fn connect(address: &str) -> io::Result<MpdPlayer> {
+        let (conn, _) = connect_mpd(address)?;
+        Ok(MpdPlayer { conn })
+    }
+
+    fn load_alarm_source(&mut self, alarm: &Alarm) -> mpd::error::Result<()> {
+        match alarm.get_source() {
+            AlarmSource::ResumeQueue => (),
+            AlarmSource::Playlist(name) => {
+                self.conn.clear()?;
+                self.conn.load(name, ..)?;
+            }
+            AlarmSource::Directory(path) => {
+                self.conn.clear()?;
+                self.conn.findadd(mpd::Query::new().and(mpd::Term::Base, path.as_str()))?;
+            }
+            AlarmSource::Stream(url) => {
+                self.conn.clear()?;
+                self.conn.push(mpd::Song { file: url.clone(), ..Default::default() })?;
+            }
+        }
+
+        if let Some(shuffle) = alarm.get_shuffle() {
+            self.conn.random(shuffle)?;
+        }
+        if let Some(repeat) = alarm.get_repeat() {
+            self.conn.repeat(repeat)?;
+        }
+        Ok(())
+    }
+}
+
+impl Player for MpdPlayer {
+    fn status(&mut self) -> io::Result<PlayerStatus> {
+        let status = self.conn.status().map_err(mpd_error)?;
+        Ok(PlayerStatus { playing: status.state == mpd::State::Play, volume: status.volume })
+    }
+
+    fn set_volume(&mut self, volume: i8) -> io::Result<()> {
+        self.conn.volume(volume).map_err(mpd_error)
+    }
+
+    fn play(&mut self) -> io::Result<()> {
+        self.conn.play().map_err(mpd_error)
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        self.conn.pause(true).map_err(mpd_error)
+    }
+
+    fn load_source(&mut self, alarm: &Alarm) -> io::Result<()> {
+        self.load_alarm_source(alarm).map_err(mpd_error)
+    }
+}
+
+// Delay before reconnecting to MPD, doubled after each failed attempt
+const RECONNECT_MIN_DELAY_MS: u64 = 500;
+const RECONNECT_MAX_DELAY_MS: u64 = 60_000;
+
+// How long to wait before the next attempt at connecting to MPD.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new() -> Backoff {
+        Backoff { delay: Duration::from_millis(RECONNECT_MIN_DELAY_MS) }
+    }
+
+    // Starts over with the shortest delay, after connecting.
+    fn reset(&mut self) {
+        self.delay = Duration::from_millis(RECONNECT_MIN_DELAY_MS);
+    }
+
+    // The delay after a failed attempt. Each one is twice the last.
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = min(delay * 2, Duration::from_millis(RECONNECT_MAX_DELAY_MS));
+        delay
+    }
+}
+
+/// Keeps one connection to MPD open, and reconnects with exponential
+/// backoff when it is lost. Calls fail while there is no connection.
+pub struct ReconnectingPlayer {
+    address:  String,
+    conn:     Option<MpdPlayer>,
+    retry_at: Instant,
+    backoff:  Backoff,
+}
+
+impl ReconnectingPlayer {
+    pub fn new(address: &str) -> ReconnectingPlayer {
+        ReconnectingPlayer {
+            address:  address.to_string(),
+            conn:     None,
+            retry_at: Instant::now(),
+            backoff:  Backoff::new(),
+        }
+    }
+
+    fn connection(&mut self) -> io::Result<&mut MpdPlayer> {
+        if self.conn.is_none() {
+            let now = Instant::now();
+            if now < self.retry_at {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "not connected to MPD"));
+            }
+
+            match MpdPlayer::connect(&self.address) {
+                Ok(player) => {
+                    info!("Connected to MPD at {}", self.address);
+                    self.conn = Some(player);
+                    self.backoff.reset();
+                }
+                Err(e) => {
+                    let delay = self.backoff.next_delay();
+                    warn!("Failed connecting to MPD at {} ({}), retrying in {:?}", self.address, e, delay);
+                    self.retry_at = now + delay;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+
+    fn call<T, F>(&mut self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut MpdPlayer) -> io::Result<T>,
+    {
+        let result = f(self.connection()?);
+        if let Err(ref e) = result {
+            // start over with a fresh connection, the next call reconnects
+            warn!("MPD command failed, dropping the connection: {}", e);
+            self.conn = None;
+        }
+        result
+    }
+}
+
+impl Player for ReconnectingPlayer {
+    fn status(&mut self) -> io::Result<PlayerStatus> {
+        self.call(|p| p.status())
+    }
+
+    fn set_volume(&mut self, volume: i8) -> io::Result<()> {
+        self.call(|p| p.set_volume(volume))
+    }
+
+    fn play(&mut self) -> io::Result<()> {
+        self.call(|p| p.play())
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        self.call(|p| p.pause())
+    }
+
+    fn load_source(&mut self, alarm: &Alarm) -> io::Result<()> {
+        self.call(|p| p.load_source(alarm))
+    }
+}
+
+/// Calls `notify` from a background thread whenever MPD reports a change
+/// in playback state or volume, and when the connection is lost or made.
+pub fn watch_mpd<F>(address: &str, notify: F) -> thread::JoinHandle<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let address = address.to_string();
+    thread::spawn(move || {
+        let mut backoff = Backoff::new();
+        loop {
+            // waiting for a change may take any time, only the handshake is timed
+            match connect_mpd(&address).and_then(|(conn, handle)| handle.set_read_timeout(None).map(|_| conn)) {
+                Ok(mut conn) => {
+                    backoff.reset();
+                    notify();
+                    while conn.wait(&[Subsystem::Player, Subsystem::Mixer]).is_ok() {
+                        notify();
+                    }
+                    notify();
+                }
+                Err(_) => {
+                    thread::sleep(backoff.next_delay());
+                }
+            }
+        }
+    })
+}
+
+/// A call made on a `FakePlayer`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerCommand {
+    SetVolume(i8),
+    Play,
+    Pause,
+    LoadSource(AlarmSource),
+}
+
+/// An in-memory player that records the commands sent to it.
+pub struct FakePlayer {
+    playing:  bool,
+    volume:   i8,
+    // accept play commands without ever starting, like MPD with an empty
+    // queue or broken audio output
+    silent:   bool,
+    commands: Vec<PlayerCommand>,
+}
+
+impl FakePlayer {
+    pub fn new() -> FakePlayer {
+        FakePlayer { playing: false, volume: 50, silent: false, commands: Vec::new() }
+    }
+
+    pub fn set_silent(&mut self, silent: bool) {
+        self.silent = silent;
+        if silent {
+            self.playing = false;
+        }
+    }
+
+    /// The commands received so far, oldest first.
+    #[cfg(test)]
+    pub fn get_commands(&self) -> &[PlayerCommand] {
+        &self.commands
+    }
+
+    fn record(&mut self, command: PlayerCommand) {
+        info!("Fake player: {:?}", command);
+        self.commands.push(command);
+    }
+}
+
+impl Player for FakePlayer {
+    fn status(&mut self) -> io::Result<PlayerStatus> {
+        Ok(PlayerStatus { playing: self.playing, volume: self.volume })
+    }
+
+    fn set_volume(&mut self, volume: i8) -> io::Result<()> {
+        self.volume = volume.clamp(0, 100);
+        self.record(PlayerCommand::SetVolume(volume));
+        Ok(())
+    }
+
+    fn play(&mut self) -> io::Result<()> {
+        self.playing = !self.silent;
+        self.record(PlayerCommand::Play);
+        Ok(())
+    }
+
+    fn pause(&mut self) -> io::Result<()> {
+        self.playing = false;
+        self.record(PlayerCommand::Pause);
+        Ok(())
+    }
+
+    fn load_source(&mut self, alarm: &Alarm) -> io::Result<()> {
+        self.record(PlayerCommand::LoadSource(alarm.get_source().clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate chrono;
+
+    use self::chrono::Duration;
+    use super::*;
+    use clock::ManualClock;
+    use playback::{Action, StateMachine};
+    use test_util::{at, one_time_config, run};
+
+    #[test]
+    fn alarm_start_produces_a_rising_volume_sequence_followed_by_play() {
+        let mut config = one_time_config();
+        let mut player = FakePlayer::new();
+        let clock = ManualClock::new(at(6, 59, 0));
+        let mut machine = StateMachine::new(&config, at(6, 59, 0));
+        run(&mut machine, &mut config, &mut player, &clock, at(7, 1, 0));
+
+        let commands = player.get_commands();
+        assert_eq!(commands[0], PlayerCommand::LoadSource(AlarmSource::ResumeQueue));
+        assert_eq!(commands[1..3], [PlayerCommand::SetVolume(10), PlayerCommand::Play]);
+        let volumes: Vec<i8> = commands[3..].iter().map(|c| match *c {
+            PlayerCommand::SetVolume(volume) => volume,
+            ref other => panic!("unexpected {:?}", other),
+        }).collect();
+        assert!(volumes.windows(2).all(|w| w[1] >= w[0]), "{:?}", volumes);
+        assert_eq!(volumes.last(), Some(&70));
+        assert!(player.status().unwrap().playing);
+    }
+
+    #[test]
+    fn fallback_sounds_when_the_player_stays_silent() {
+        let mut config = one_time_config();
+        let due = at(7, 0, 0) + config.get_fallback_delay();
+        let mut player = FakePlayer::new();
+        player.set_silent(true);
+        let clock = ManualClock::new(at(6, 59, 0));
+        let mut machine = StateMachine::new(&config, at(6, 59, 0));
+
+        let before = run(&mut machine, &mut config, &mut player, &clock, due - Duration::milliseconds(1));
+        assert!(!before.contains(&Action::SetFallback(true)));
+        let after = run(&mut machine, &mut config, &mut player, &clock, at(7, 2, 0));
+        assert_eq!(after.iter().filter(|a| **a == Action::SetFallback(true)).count(), 1);
+        assert!(player.get_commands().contains(&PlayerCommand::Play));
+    }
+
+    #[test]
+    fn fallback_stays_off_when_the_player_plays() {
+        let mut config = one_time_config();
+        let mut player = FakePlayer::new();
+        let clock = ManualClock::new(at(6, 59, 0));
+        let mut machine = StateMachine::new(&config, at(6, 59, 0));
+
+        let others = run(&mut machine, &mut config, &mut player, &clock, at(7, 2, 0));
+        assert!(!others.iter().any(|a| matches!(a, Action::SetFallback(_))));
+    }
+}
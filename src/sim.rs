@@ -0,0 +1,172 @@
+use std::io;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use display::{LcdDevice, MockLcd, LCD_HEIGHT, LCD_WIDTH};
+use input::{InputEvent, InputSource, MockInput};
+
+// Text colors for lit pixels with the backlight on and off
+const COLOR_LIT: &str = "\x1b[97m";
+const COLOR_DIM: &str = "\x1b[90m";
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// Shows the display in the terminal, two pixel rows per text line using
+/// Unicode half blocks. It is drawn on stderr so that the log on stdout can
+/// be redirected elsewhere.
+pub struct TerminalLcd {
+    lcd:    MockLcd,
+    legend: String,
+}
+
+impl TerminalLcd {
+    pub fn new(legend: String) -> TerminalLcd {
+        // clear the screen once, later redraws only move the cursor home
+        eprint!("\x1b[2J");
+        TerminalLcd { lcd: MockLcd::new(), legend }
+    }
+
+    fn render(&self) -> io::Result<()> {
+        let color = if self.lcd.get_backlight() { COLOR_LIT } else { COLOR_DIM };
+        let mut out = String::new();
+
+        out.push_str("\x1b[H");
+        out.push_str(&format!("+{}+\n", "-".repeat(LCD_WIDTH)));
+        for y in (0..LCD_HEIGHT).step_by(2) {
+            out.push('|');
+            out.push_str(color);
+            for x in 0..LCD_WIDTH {
+                out.push(match (self.lcd.pixel(x, y), self.lcd.pixel(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            out.push_str(COLOR_RESET);
+            out.push_str("|\n");
+        }
+        out.push_str(&format!("+{}+\n", "-".repeat(LCD_WIDTH)));
+        out.push_str(&self.legend);
+        out.push_str("\x1b[K\n");
+
+        let stderr = io::stderr();
+        let mut handle = stderr.lock();
+        handle.write_all(out.as_bytes())?;
+        handle.flush()
+    }
+}
+
+impl LcdDevice for TerminalLcd {
+    fn get_backlight(&self) -> bool {
+        self.lcd.get_backlight()
+    }
+
+    fn set_backlight(&mut self, on: bool) -> io::Result<()> {
+        if on != self.lcd.get_backlight() {
+            self.lcd.set_backlight(on)?;
+            self.render()?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.lcd.clear()?;
+        self.render()
+    }
+
+    fn set_bytes_at(&mut self, row: usize, col: usize, bytes: &[u8]) -> io::Result<usize> {
+        let n_written = self.lcd.set_bytes_at(row, col, bytes)?;
+        self.render()?;
+        Ok(n_written)
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("stty failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads key presses from the terminal. Keys in `buttons` press the button
+/// on the given pin, the arrow keys and +/- turn the rotary encoder.
+pub struct KeyboardInput {
+    input:     MockInput,
+    saved_tty: String,
+}
+
+impl KeyboardInput {
+    pub fn new(buttons: &[(char, u8)]) -> io::Result<KeyboardInput> {
+        let saved_tty = stty(&["-g"])?;
+        // deliver every key press right away, without echoing it
+        stty(&["-icanon", "-echo", "min", "1"])?;
+
+        let input = MockInput::new();
+        let tx = input.sender();
+        let buttons = buttons.to_vec();
+
+        thread::spawn(move || {
+            let mut escape: Vec<u8> = Vec::new();
+
+            // the lock is buffered, and nothing else reads from stdin
+            for byte in io::stdin().lock().bytes() {
+                let byte = match byte {
+                    Ok(b) => b,
+                    Err(_) => break,
+                };
+
+                // arrow keys arrive as the sequence ESC [ A..D
+                if byte == 0x1b || !escape.is_empty() {
+                    escape.push(byte);
+                    let event = match escape.as_slice() {
+                        [0x1b] | [0x1b, b'['] => continue,
+                        [0x1b, b'[', b'A'] | [0x1b, b'[', b'C'] => Some(InputEvent::RotaryEncoder(1)),
+                        [0x1b, b'[', b'B'] | [0x1b, b'[', b'D'] => Some(InputEvent::RotaryEncoder(-1)),
+                        _ => None,
+                    };
+                    escape.clear();
+                    if let Some(event) = event {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+
+                let key = byte as char;
+                let event = match key {
+                    '+' | '=' => Some(InputEvent::RotaryEncoder(1)),
+                    '-' => Some(InputEvent::RotaryEncoder(-1)),
+                    _ => buttons.iter().find(|&&(k, _)| k == key).map(|&(_, pin)| InputEvent::Button(pin)),
+                };
+                if let Some(event) = event {
+                    if tx.send(event).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(KeyboardInput { input, saved_tty })
+    }
+}
+
+impl InputSource for KeyboardInput {
+    fn handle_events(&mut self, callback: &mut dyn FnMut(InputEvent)) {
+        self.input.handle_events(callback)
+    }
+
+    fn start(&mut self, callback: Box<dyn Fn(InputEvent) + Send>) {
+        self.input.start(callback)
+    }
+}
+
+impl Drop for KeyboardInput {
+    fn drop(&mut self) {
+        if let Err(e) = stty(&[&self.saved_tty]) {
+            error!("Failed restoring terminal settings: {}", e);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+extern crate inotify;
+
+use self::inotify::{Inotify, WatchMask};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::thread;
+use std::time::Duration;
+
+use config::{backup_path, Config};
+
+// How often to look for changes made to the config file by others, when
+// inotify isn't available
+const WATCH_INTERVAL_MS: u64 = 2000;
+
+// Hash of the contents of a file, to tell whether it has changed. Unlike the
+// modification time, this also catches an edit made within the timestamp
+// granularity of the file system that keeps the size.
+type FileStamp = u64;
+
+fn file_stamp(path: &str) -> Option<FileStamp> {
+    let contents = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+// The config file as it was last read or written here
+struct FileState {
+    stamp: Option<FileStamp>,
+    // whether the file could be read, so that saving over it loses nothing
+    valid: bool,
+}
+
+/// The config shared by the main loop and the web UI, together with the
+/// file it is kept in. All changes go through `update`, which saves them.
+pub struct ConfigStore {
+    config: RwLock<Config>,
+    path:   String,
+    file:   Mutex<FileState>,
+}
+
+impl ConfigStore {
+    /// Reads the config from `path`. If that file is damaged, the backup
+    /// made by the last save is used instead, and if there is no usable
+    /// file at all, the defaults.
+    pub fn load(path: &str) -> ConfigStore {
+        let config = match Config::read_new(path) {
+            Ok(c) => {
+                info!("Reading config from file at {}", path);
+                c
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound && !Path::new(&backup_path(path)).exists() => {
+                warn!("No config file found at {}, using the defaults", path);
+                Config::default()
+            }
+            Err(e) => {
+                error!("Failed reading config from {}: {}", path, e);
+                recover(path)
+            }
+        };
+        let file = FileState { stamp: file_stamp(path), valid: true };
+        ConfigStore { config: RwLock::new(config), path: path.to_string(), file: Mutex::new(file) }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Config> {
+        self.config.read().unwrap()
+    }
+
+    /// Applies `f` to the config and saves it if anything changed. The
+    /// lock is held until the file is written, so that concurrent changes
+    /// are saved in the order they were made. Changes made to the file by
+    /// others are read first, so that they are not overwritten, and if the
+    /// changed file is invalid it is not saved over at all. A change that
+    /// fails to save is still kept in memory, and the error is returned
+    /// alongside the result of `f`.
+    pub fn update<R, F: FnOnce(&mut Config) -> R>(&self, f: F) -> (R, io::Result<()>) {
+        // lock the config first, so that a save in progress is not taken for
+        // an edit made by someone else
+        let mut config = self.config.write().unwrap();
+        let mut file = self.file.lock().unwrap();
+        self.reload_locked(&mut config, &mut file);
+
+        let before = config.clone();
+        let result = f(&mut config);
+
+        if *config == before {
+            return (result, Ok(()));
+        }
+        if !file.valid && file.stamp.is_some() {
+            error!("Not saving config to {}, which was changed by someone else and is invalid", self.path);
+            let e = io::Error::other("the config file was changed by someone else and is invalid");
+            return (result, Err(e));
+        }
+        let saved = config.write(&self.path);
+        if let Err(ref e) = saved {
+            error!("Failed saving config to {}: {}", self.path, e);
+        }
+        *file = FileState { stamp: file_stamp(&self.path), valid: true };
+        (result, saved)
+    }
+
+    /// Reads the config file again if it has been changed since it was last
+    /// read or written here. A file that fails to read or validate is
+    /// ignored, keeping the current config. Returns whether the config
+    /// changed.
+    pub fn reload_if_changed(&self) -> bool {
+        let mut config = self.config.write().unwrap();
+        let mut file = self.file.lock().unwrap();
+        self.reload_locked(&mut config, &mut file)
+    }
+
+    fn reload_locked(&self, config: &mut Config, file: &mut FileState) -> bool {
+        let current = file_stamp(&self.path);
+        // a removed file is left alone, the next save writes it again
+        if current.is_none() || current == file.stamp {
+            return false;
+        }
+        file.stamp = current;
+
+        let new_config = match Config::read_new(&self.path) {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Ignoring the changed config file at {}, keeping the current config: {}", self.path, e);
+                file.valid = false;
+                return false;
+            }
+        };
+        file.valid = true;
+        if new_config == *config {
+            return false;
+        }
+
+        info!("Reloaded the changed config file at {}", self.path);
+        let restart_needed = new_config.mpd_host != config.mpd_host
+            || new_config.mpd_port != config.mpd_port
+            || new_config.web_bind != config.web_bind
+            || new_config.log_level != config.log_level
+            || new_config.frontend != config.frontend
+            || new_config.hardware != config.hardware
+            || new_config.fallback_command != config.fallback_command;
+        if restart_needed {
+            warn!("Changes to the MPD address, web UI address, log level, frontend, hardware or fallback command \
+                   take effect after a restart");
+        }
+        *config = new_config;
+        true
+    }
+}
+
+/// Checks the config file for changes made by others, e.g. by editing it by
+/// hand, from a background thread, as soon as inotify reports them. Changes
+/// are applied to `store`, after which `notify` is called.
+pub fn watch_config<F>(store: Arc<ConfigStore>, notify: F) -> thread::JoinHandle<()>
+where
+    F: Fn() + Send + 'static,
+{
+    thread::spawn(move || {
+        if let Err(e) = watch_with_inotify(&store, &notify) {
+            warn!("Can't watch the config file with inotify ({}), checking it every {} ms instead", e,
+                  WATCH_INTERVAL_MS);
+        }
+        loop {
+            thread::sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+            if store.reload_if_changed() {
+                notify();
+            }
+        }
+    })
+}
+
+// Waits for the config file to be written or replaced. The directory holding
+// it is watched rather than the file, since saves rename a new file over it.
+// Only returns if watching fails.
+fn watch_with_inotify<F: Fn()>(store: &ConfigStore, notify: &F) -> io::Result<()> {
+    let path = Path::new(&store.path);
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+    let mut buffer = [0; 4096];
+    loop {
+        let events = inotify.read_events_blocking(&mut buffer)?;
+        let changed = events.into_iter().any(|event| event.name == Some(name));
+        // our own saves are told apart by the contents, which match the stamp
+        if changed && store.reload_if_changed() {
+            notify();
+        }
+    }
+}
+
+// Falls back to the backup of a config file that was damaged or invalid. The
+// damaged file is moved aside, so that the next save neither overwrites it
+// nor copies it over the backup.
+fn recover(path: &str) -> Config {
+    if Path::new(path).exists() {
+        let damaged_path = format!("{}.damaged", path);
+        match fs::rename(path, &damaged_path) {
+            Ok(()) => warn!("Moved the rejected config file to {}", damaged_path),
+            Err(e) => error!("Failed moving the rejected config file to {}: {}", damaged_path, e),
+        }
+    }
+
+    let backup = backup_path(path);
+    match Config::read_new(&backup) {
+        Ok(c) => {
+            warn!("Recovered the config from the backup at {}", backup);
+            c
+        }
+        Err(e) => {
+            error!("Failed reading the config backup at {} ({}), using the defaults", backup, e);
+            Config::default()
+        }
+    }
+}
@@ -0,0 +1,57 @@
+extern crate chrono;
+
+use self::chrono::{DateTime, Local, TimeZone};
+use std::time;
+
+use alarm::{Alarm, AlarmMode, Time};
+use clock::{Clock, ManualClock};
+use config::Config;
+use player::{FakePlayer, Player};
+use playback::{Action, StateMachine};
+
+/// `hour:min:sec` on Wednesday 10 January 2024, a day without DST changes
+/// anywhere.
+pub fn at(hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+    on(10, hour, min, sec)
+}
+
+/// `hour:min:sec` on the given day of January 2024. The 8th is a Monday.
+pub fn on(day: u32, hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+    Local.with_ymd_and_hms(2024, 1, day, hour, min, sec).unwrap()
+}
+
+/// An alarm at 07:00 fading from 10% to 70% over a minute.
+pub fn alarm_at_seven(mode: AlarmMode) -> Alarm {
+    Alarm::new("Test".to_string(), true, Time::new(7, 0).unwrap(), 60, 0.1, 0.7, mode)
+}
+
+/// The default settings with a one-time `alarm_at_seven` as the only alarm.
+pub fn one_time_config() -> Config {
+    Config { alarms: vec![alarm_at_seven(AlarmMode::OneTime)], ..Config::default() }
+}
+
+/// Runs the main loop against `clock` until `until`: each tick passes the
+/// player's status to `machine` and its actions on to `player`, then the
+/// clock jumps to the next deadline. Returns the actions that aren't for
+/// the player.
+pub fn run(machine: &mut StateMachine, config: &mut Config, player: &mut FakePlayer, clock: &ManualClock,
+           until: DateTime<Local>) -> Vec<Action> {
+    let mut others = Vec::new();
+    while clock.now() <= until {
+        let now = clock.now();
+        let status = player.status().unwrap();
+        for action in machine.update(config, Some(status), &[], now) {
+            match action {
+                Action::LoadSource(alarm) => player.load_source(&alarm).unwrap(),
+                Action::SetVolume(volume) => player.set_volume(volume).unwrap(),
+                Action::Play => player.play().unwrap(),
+                Action::Pause => player.pause().unwrap(),
+                other => others.push(other),
+            }
+        }
+        // always move on, so that a deadline which is due already can't stall the loop
+        let timeout = (machine.next_deadline(config, &now) - now).to_std().unwrap_or(time::Duration::from_millis(0));
+        clock.real_timeout(timeout.max(time::Duration::from_millis(1)));
+    }
+    others
+}
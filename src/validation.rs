@@ -0,0 +1,62 @@
+extern crate chrono;
+extern crate serde_json;
+
+use self::chrono::Duration;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Why settings from the config file or the web UI were rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// The settings couldn't be parsed at all.
+    Syntax(String),
+    /// A setting has a value outside of what it can be.
+    OutOfRange { field: &'static str, value: String, expected: &'static str },
+    /// One of the alarms is invalid, counting from zero.
+    Alarm { index: usize, error: Box<ValidationError> },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::Syntax(ref msg) => write!(f, "{}", msg),
+            ValidationError::OutOfRange { field, ref value, expected } => {
+                write!(f, "{} is {}, expected {}", field, value, expected)
+            }
+            ValidationError::Alarm { index, ref error } => write!(f, "alarm {}: {}", index + 1, error),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl From<serde_json::Error> for ValidationError {
+    fn from(e: serde_json::Error) -> Self {
+        ValidationError::Syntax(e.to_string())
+    }
+}
+
+impl From<ValidationError> for io::Error {
+    fn from(e: ValidationError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// `value_s` seconds as a duration, limited to `0..=max_s`. Settings are
+/// checked against the same limits, so this only matters for ones that
+/// never went through `check`, which could otherwise overflow chrono.
+pub fn bounded_seconds(value_s: i64, max_s: i64) -> Duration {
+    Duration::try_seconds(value_s.max(0).min(max_s)).unwrap_or_else(Duration::zero)
+}
+
+/// Fails with `OutOfRange` unless `ok` holds.
+pub fn check<T: fmt::Display>(ok: bool, field: &'static str, value: T, expected: &'static str)
+                              -> Result<(), ValidationError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange { field, value: value.to_string(), expected })
+    }
+}
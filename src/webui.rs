@@ -4,25 +4,59 @@ extern crate serde;
 extern crate chrono;
 
 use self::serde::Serialize;
-use self::serde_json::Number;
+use self::serde_json::{Number, Value};
 use self::tinytemplate::TinyTemplate;
+use self::chrono::{DateTime, Local};
 
+use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::thread;
-use std::sync::{Arc,RwLock};
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
 
-use config::Config;
+use clock::Clock;
+use config::{Config, MAX_SLEEP_TIMER_MIN};
+use store::ConfigStore;
 use alarm::Alarm;
 use alarm::AlarmMode;
+use alarm::AlarmSource;
 use alarm::DayMask;
+use alarm::Date;
 use alarm::Time;
+use fade::FadeCurve;
+use player::{connect_mpd, mpd_error};
+use validation::check;
+
+/// Requests from the web UI that act on the player rather than the config,
+/// and notice of changes made to the config.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebCommand {
+    StartSleepTimer(i64),
+    CancelSleepTimer,
+    ConfigChanged,
+}
 
 #[derive(Serialize)]
-struct Context {
+struct PlaylistOption {
+    name: String,
+    selected: String,
+}
+
+#[derive(Serialize)]
+struct AlarmContext {
+    alarm_index: usize,
+    alarm_revision: String,
+    alarm_name: String,
     alarm_enabled_checked: String,
     alarm_mode_onetime_checked: String,
     alarm_mode_recurring_checked: String,
+    alarm_mode_dated_checked: String,
     alarm_daymask_disabled: String,
+    alarm_date_disabled: String,
+    alarm_date: String,
     alarm_daymask_mon_checked: String,
     alarm_daymask_tue_checked: String,
     alarm_daymask_wed_checked: String,
@@ -30,20 +64,107 @@ struct Context {
     alarm_daymask_fri_checked: String,
     alarm_daymask_sat_checked: String,
     alarm_daymask_sun_checked: String,
+    alarm_skip_next_checked: String,
+    alarm_source_resume_selected: String,
+    alarm_source_playlist_selected: String,
+    alarm_source_directory_selected: String,
+    alarm_source_stream_selected: String,
+    alarm_source_playlists: Vec<PlaylistOption>,
+    alarm_source_path: String,
+    alarm_shuffle_keep_selected: String,
+    alarm_shuffle_on_selected: String,
+    alarm_shuffle_off_selected: String,
+    alarm_repeat_keep_selected: String,
+    alarm_repeat_on_selected: String,
+    alarm_repeat_off_selected: String,
     alarm_time: String,
+    alarm_next: String,
     alarm_start_vol: Number,
     alarm_end_vol: Number,
-    alarm_fade_length_s: Number
+    alarm_fade_length_s: Number,
+    alarm_fade_curve_linear_selected: String,
+    alarm_fade_curve_exponential_selected: String,
+    alarm_fade_curve_scurve_selected: String,
+    alarm_fade_curve_stepped_selected: String,
+    alarm_fade_steps: Number,
+    alarm_snooze_length_min: Number,
+    alarm_max_snoozes: Number,
+    alarm_auto_stop_min: Number,
+    alarm_auto_stop_fade_s: Number
 }
 
+#[derive(Serialize)]
+struct Context {
+    alarms: Vec<AlarmContext>,
+    sleep_timer_min: Number
+}
 
-fn create_page(alarm: &Alarm) -> String {
-    let mut tt = TinyTemplate::new();
-    tt.add_template("form", TEMPLATE).expect("Failed adding template");
+// Identifies the list of alarms a page was made from, so that a form posted
+// after alarms were added, deleted or changed elsewhere is refused rather
+// than applied to whichever alarm is now at its position. Leaves out when
+// each alarm last went off, which changes without anyone editing it.
+fn alarms_revision(config: &Config) -> String {
+    let mut hasher = DefaultHasher::new();
+    for alarm in &config.alarms {
+        let mut value = serde_json::to_value(alarm).unwrap_or(Value::Null);
+        if let Value::Object(ref mut fields) = value {
+            fields.remove("last_fired");
+        }
+        value.to_string().hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+// What became of a change to one of the alarms.
+enum AlarmEdit {
+    Done,
+    Missing,
+    Stale,
+}
+
+fn stale_response() -> rouille::Response {
+    rouille::Response::text("The alarms were changed since the page was loaded, reload it and try again")
+        .with_status_code(409)
+}
 
+fn describe_next_occurrence(alarm: &Alarm, now: &DateTime<Local>) -> String {
+    match alarm.next_occurrence(now) {
+        Some(next) => {
+            let until = next - *now;
+            let countdown = if until.num_days() > 0 {
+                format!("{}d {}h {}m", until.num_days(), until.num_hours() % 24, until.num_minutes() % 60)
+            } else {
+                format!("{}h {}m", until.num_hours(), until.num_minutes() % 60)
+            };
+            format!("{} (in {})", next.format("%a %Y-%m-%d %H:%M"), countdown)
+        }
+        None => "Not scheduled".to_string()
+    }
+}
+
+fn selected_if(cond: bool) -> String {
+    (if cond { "selected" } else { "" }).to_string()
+}
+
+// Names of the playlists stored in mpd, or none if mpd can't be reached.
+fn mpd_playlists(mpd_address: &str) -> Vec<String> {
+    match connect_mpd(mpd_address).and_then(|(mut conn, _)| conn.playlists().map_err(mpd_error)) {
+        Ok(playlists) => playlists.into_iter().map(|p| p.name).collect(),
+        Err(e) => {
+            warn!("Failed listing mpd playlists: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn create_alarm_context(alarm_index: usize, alarm_revision: String, alarm: &Alarm, playlists: &[String],
+                        now: &DateTime<Local>) -> AlarmContext {
     let mut alarm_mode_recurring_checked = "".to_string();
     let mut alarm_mode_onetime_checked   = "".to_string();
+    let mut alarm_mode_dated_checked     = "".to_string();
     let mut alarm_daymask_disabled       = "".to_string();
+    let mut alarm_date_disabled          = "".to_string();
+    let mut alarm_date                   = now.format("%Y-%m-%d").to_string();
     let mut alarm_daymask_mon_checked    = "".to_string();
     let mut alarm_daymask_tue_checked    = "".to_string();
     let mut alarm_daymask_wed_checked    = "".to_string();
@@ -56,9 +177,16 @@ fn create_page(alarm: &Alarm) -> String {
         AlarmMode::OneTime => {
             alarm_mode_onetime_checked   = "checked".to_string();
             alarm_daymask_disabled       = "disabled".to_string();
+            alarm_date_disabled          = "disabled".to_string();
+        }
+        AlarmMode::Dated(date) => {
+            alarm_mode_dated_checked     = "checked".to_string();
+            alarm_daymask_disabled       = "disabled".to_string();
+            alarm_date                   = date.to_str();
         }
         AlarmMode::Recurring(dm) => {
             alarm_mode_recurring_checked = "checked".to_string();
+            alarm_date_disabled          = "disabled".to_string();
 
             if dm.contains(DayMask::MONDAY)    { alarm_daymask_mon_checked = "checked".to_string() }
             if dm.contains(DayMask::TUESDAY)   { alarm_daymask_tue_checked = "checked".to_string() }
@@ -70,11 +198,51 @@ fn create_page(alarm: &Alarm) -> String {
         }
     }
 
-    let context = Context {
+    let mut alarm_fade_curve_linear_selected      = "".to_string();
+    let mut alarm_fade_curve_exponential_selected = "".to_string();
+    let mut alarm_fade_curve_scurve_selected      = "".to_string();
+    let mut alarm_fade_curve_stepped_selected     = "".to_string();
+    let mut alarm_fade_steps                      = 5;
+
+    match alarm.get_fade_curve() {
+        FadeCurve::Linear      => alarm_fade_curve_linear_selected = "selected".to_string(),
+        FadeCurve::Exponential => alarm_fade_curve_exponential_selected = "selected".to_string(),
+        FadeCurve::SCurve      => alarm_fade_curve_scurve_selected = "selected".to_string(),
+        FadeCurve::Stepped(n)  => {
+            alarm_fade_curve_stepped_selected = "selected".to_string();
+            alarm_fade_steps = n;
+        }
+    }
+
+    let source = alarm.get_source();
+    let (current_playlist, alarm_source_path) = match source {
+        AlarmSource::ResumeQueue => (None, "".to_string()),
+        AlarmSource::Playlist(name) => (Some(name), "".to_string()),
+        AlarmSource::Directory(path) => (None, path.clone()),
+        AlarmSource::Stream(url) => (None, url.clone()),
+    };
+
+    let mut alarm_source_playlists: Vec<PlaylistOption> = playlists
+        .iter()
+        .map(|name| PlaylistOption { name: name.clone(), selected: selected_if(Some(name) == current_playlist) })
+        .collect();
+    if let Some(name) = current_playlist {
+        if !playlists.contains(name) {
+            alarm_source_playlists.push(PlaylistOption { name: name.clone(), selected: selected_if(true) });
+        }
+    }
+
+    AlarmContext {
+        alarm_index,
+        alarm_revision,
+        alarm_name:                alarm.get_name().to_string(),
         alarm_enabled_checked: (if alarm.is_enabled() { "checked" } else { "" }).to_string(),
         alarm_mode_onetime_checked,
         alarm_mode_recurring_checked,
+        alarm_mode_dated_checked,
         alarm_daymask_disabled,
+        alarm_date_disabled,
+        alarm_date,
         alarm_daymask_mon_checked,
         alarm_daymask_tue_checked,
         alarm_daymask_wed_checked,
@@ -82,32 +250,134 @@ fn create_page(alarm: &Alarm) -> String {
         alarm_daymask_fri_checked,
         alarm_daymask_sat_checked,
         alarm_daymask_sun_checked,
+        alarm_skip_next_checked:   (if alarm.is_skipping_next() { "checked" } else { "" }).to_string(),
+        alarm_source_resume_selected:    selected_if(*source == AlarmSource::ResumeQueue),
+        alarm_source_playlist_selected:  selected_if(matches!(source, AlarmSource::Playlist(_))),
+        alarm_source_directory_selected: selected_if(matches!(source, AlarmSource::Directory(_))),
+        alarm_source_stream_selected:    selected_if(matches!(source, AlarmSource::Stream(_))),
+        alarm_source_playlists,
+        alarm_source_path,
+        alarm_shuffle_keep_selected:     selected_if(alarm.get_shuffle().is_none()),
+        alarm_shuffle_on_selected:       selected_if(alarm.get_shuffle() == Some(true)),
+        alarm_shuffle_off_selected:      selected_if(alarm.get_shuffle() == Some(false)),
+        alarm_repeat_keep_selected:      selected_if(alarm.get_repeat().is_none()),
+        alarm_repeat_on_selected:        selected_if(alarm.get_repeat() == Some(true)),
+        alarm_repeat_off_selected:       selected_if(alarm.get_repeat() == Some(false)),
         alarm_time:                alarm.get_time().to_str(),
+        alarm_next:                describe_next_occurrence(alarm, now),
         alarm_fade_length_s:       Number::from_f64(alarm.get_length().num_seconds() as f64).unwrap(),
+        alarm_fade_curve_linear_selected,
+        alarm_fade_curve_exponential_selected,
+        alarm_fade_curve_scurve_selected,
+        alarm_fade_curve_stepped_selected,
+        alarm_fade_steps:          Number::from(alarm_fade_steps),
         alarm_start_vol:           Number::from_f64((alarm.get_start_vol()*100.0).round() as f64).unwrap(),
         alarm_end_vol:             Number::from_f64((alarm.get_end_vol()*100.0).round() as f64).unwrap(),
+        alarm_snooze_length_min:   Number::from(alarm.get_snooze_length().num_minutes()),
+        alarm_max_snoozes:         Number::from(alarm.get_max_snoozes()),
+        alarm_auto_stop_min:       Number::from(alarm.get_auto_stop().map_or(0, |d| d.num_minutes())),
+        alarm_auto_stop_fade_s:    Number::from(alarm.get_auto_stop_fade().num_seconds()),
+    }
+}
+
+fn create_page(config: &Config, playlists: &[String], now: &DateTime<Local>) -> String {
+    let mut tt = TinyTemplate::new();
+    tt.add_template("form", TEMPLATE).expect("Failed adding template");
+
+    let revision = alarms_revision(config);
+    let context = Context {
+        alarms: config.alarms.iter().enumerate()
+            .map(|(i, a)| create_alarm_context(i, revision.clone(), a, playlists, now))
+            .collect(),
+        sleep_timer_min: Number::from(config.sleep_timer_min)
     };
 
     tt.render("form", &context).expect("Failed rendering template")
 }
 
-pub fn start_webui(config: Arc<RwLock<Config>>) -> thread::JoinHandle<()> {
-    thread::spawn( || {
-        println!("Starting web UI server listening on 0.0.0.0:8000");
+// Back to the home page once a change has been saved, or an error page
+// telling that it only lasts until the next restart.
+fn saved_response(saved: io::Result<()>) -> rouille::Response {
+    match saved {
+        Ok(()) => rouille::Response::redirect_303("/"),
+        Err(e) => rouille::Response::text(format!("The change was applied, but saving it failed: {}", e))
+            .with_status_code(500),
+    }
+}
+
+pub fn start_webui(config: Arc<ConfigStore>, commands: Sender<WebCommand>, playing: Arc<AtomicBool>,
+                   clock: Arc<dyn Clock>, address: String, mpd_address: String) -> thread::JoinHandle<()> {
+    let commands = Mutex::new(commands);
+    thread::spawn(move || {
+        info!("Starting web UI server listening on {}", address);
 
-        rouille::start_server("0.0.0.0:8000", move |request| {
+        rouille::start_server(address, move |request| {
             rouille::log(&request, io::stdout(), || {
 
 
                 router!(request,
                         (GET) (/) => {
-                            let page = create_page(&config.read().unwrap().alarm);
+                            // ask mpd before locking the config, so that a slow mpd can't hold up the main loop
+                            let playlists = mpd_playlists(&mpd_address);
+                            let page = create_page(&config.read(), &playlists, &clock.now());
                             rouille::Response::html(page)
                         },
 
-                        (POST) (/) => {
-                            // This is the route that is called when the user submits the form of the
-                            // home page.
+                        (POST) (/sleep) => {
+                            let data = try_or_400!(post_input!(request, {
+                                sleep_timer_min: i64,
+                            }));
+                            let minutes = data.sleep_timer_min;
+                            let in_range = minutes > 0 && minutes <= MAX_SLEEP_TIMER_MIN;
+                            let expected = "more than zero, up to 24 hours";
+                            if let Err(e) = check(in_range, "sleep timer", format!("{} min", minutes), expected) {
+                                return rouille::Response::text(format!("Invalid sleep timer: {}", e)).with_status_code(400);
+                            }
+                            if !playing.load(Ordering::Relaxed) {
+                                return rouille::Response::text("Nothing is playing, so the sleep timer was not started")
+                                    .with_status_code(409);
+                            }
+                            commands.lock().unwrap().send(WebCommand::StartSleepTimer(data.sleep_timer_min)).unwrap();
+                            rouille::Response::redirect_303("/")
+                        },
+
+                        (POST) (/sleep/cancel) => {
+                            commands.lock().unwrap().send(WebCommand::CancelSleepTimer).unwrap();
+                            rouille::Response::redirect_303("/")
+                        },
+
+                        (POST) (/alarm/new) => {
+                            let (_, saved) = config.update(|cfg| cfg.alarms.push(Alarm::default()));
+                            commands.lock().unwrap().send(WebCommand::ConfigChanged).unwrap();
+                            saved_response(saved)
+                        },
+
+                        (POST) (/alarm/{index: usize}/delete) => {
+                            let data = try_or_400!(post_input!(request, {
+                                alarm_revision: String,
+                            }));
+                            let (edit, saved) = config.update(|cfg| {
+                                if alarms_revision(cfg) != data.alarm_revision {
+                                    AlarmEdit::Stale
+                                } else if index < cfg.alarms.len() {
+                                    cfg.alarms.remove(index);
+                                    AlarmEdit::Done
+                                } else {
+                                    AlarmEdit::Missing
+                                }
+                            });
+                            match edit {
+                                AlarmEdit::Done => (),
+                                AlarmEdit::Missing => return rouille::Response::empty_404(),
+                                AlarmEdit::Stale => return stale_response(),
+                            }
+                            commands.lock().unwrap().send(WebCommand::ConfigChanged).unwrap();
+                            saved_response(saved)
+                        },
+
+                        (POST) (/alarm/{index: usize}) => {
+                            // This is the route that is called when the user submits the form of
+                            // one of the alarms on the home page.
 
                             // We query the data with the `post_input!` macro. Each field of the macro
                             // corresponds to an element of the form.
@@ -115,10 +385,13 @@ pub fn start_webui(config: Arc<RwLock<Config>>) -> thread::JoinHandle<()> {
                             // can happen if you screw up the form or if the user made a manual request)
                             // we return a 400 response.
                             let data = try_or_400!(post_input!(request, {
+                                alarm_revision: String,
+                                alarm_name: String,
                                 alarm_enabled: bool,
 
                                 alarm_time: String,
                                 alarm_mode: String,
+                                alarm_date: Option<String>,
                                 alarm_daymask_mon: bool,
                                 alarm_daymask_tue: bool,
                                 alarm_daymask_wed: bool,
@@ -126,10 +399,25 @@ pub fn start_webui(config: Arc<RwLock<Config>>) -> thread::JoinHandle<()> {
                                 alarm_daymask_fri: bool,
                                 alarm_daymask_sat: bool,
                                 alarm_daymask_sun: bool,
+                                alarm_skip_next: bool,
+
+                                alarm_source: String,
+                                alarm_source_playlist: Option<String>,
+                                alarm_source_path: String,
+                                alarm_shuffle: String,
+                                alarm_repeat: String,
 
                                 alarm_start_vol: u8,
                                 alarm_end_vol: u8,
                                 alarm_fade_length_s: i64,
+                                alarm_fade_curve: String,
+                                alarm_fade_steps: u8,
+
+                                alarm_snooze_length_min: i64,
+                                alarm_max_snoozes: u32,
+
+                                alarm_auto_stop_min: i64,
+                                alarm_auto_stop_fade_s: i64,
                             }));
 
                             let mode = if data.alarm_mode == "recurring" {
@@ -144,23 +432,88 @@ pub fn start_webui(config: Arc<RwLock<Config>>) -> thread::JoinHandle<()> {
 
                                 AlarmMode::Recurring(mask)
                             }
+                            else if data.alarm_mode == "dated" {
+                                match data.alarm_date.as_ref().map(|d| Date::try_from(d.as_str())) {
+                                    Some(Ok(date)) => AlarmMode::Dated(date),
+                                    Some(Err(e)) => return rouille::Response::text(format!("Invalid alarm date: {}", e)).with_status_code(400),
+                                    None => return rouille::Response::text("Invalid alarm date: no date given").with_status_code(400)
+                                }
+                            }
                             else {
                                 AlarmMode::OneTime
                             };
 
-                            config.write().unwrap().alarm = Alarm::new(data.alarm_enabled,
-                                                                       Time::from_str(&data.alarm_time),
-                                                                       data.alarm_fade_length_s,
-                                                                       (data.alarm_start_vol as f32)/100.0,
-                                                                       (data.alarm_end_vol as f32)/100.0,
-                                                                       mode);
-
+                            let time = match Time::try_from(data.alarm_time.as_str()) {
+                                Ok(time) => time,
+                                Err(e) => return rouille::Response::text(format!("Invalid alarm time: {}", e)).with_status_code(400)
+                            };
+                            let mut alarm = Alarm::new(data.alarm_name.clone(),
+                                                       data.alarm_enabled,
+                                                       time,
+                                                       data.alarm_fade_length_s,
+                                                       (data.alarm_start_vol as f32)/100.0,
+                                                       (data.alarm_end_vol as f32)/100.0,
+                                                       mode);
+                            alarm.set_fade_curve(match data.alarm_fade_curve.as_str() {
+                                "exponential" => FadeCurve::Exponential,
+                                "scurve" => FadeCurve::SCurve,
+                                "stepped" => FadeCurve::Stepped(data.alarm_fade_steps),
+                                _ => FadeCurve::Linear
+                            });
+                            let source = match data.alarm_source.as_str() {
+                                "playlist" => match data.alarm_source_playlist {
+                                    Some(ref name) => AlarmSource::Playlist(name.clone()),
+                                    None => return rouille::Response::text("No playlist selected").with_status_code(400)
+                                },
+                                "directory" => AlarmSource::Directory(data.alarm_source_path.clone()),
+                                "stream" => AlarmSource::Stream(data.alarm_source_path.clone()),
+                                _ => AlarmSource::ResumeQueue
+                            };
+                            let parse_flag = |flag: &str| match flag {
+                                "on" => Some(true),
+                                "off" => Some(false),
+                                _ => None
+                            };
+                            alarm.set_source(source, parse_flag(&data.alarm_shuffle), parse_flag(&data.alarm_repeat));
+                            alarm.set_skip_next(data.alarm_skip_next);
+                            let (snooze_length_s, auto_stop_s) = match (data.alarm_snooze_length_min.checked_mul(60),
+                                                                        data.alarm_auto_stop_min.checked_mul(60)) {
+                                (Some(snooze_s), Some(stop_s)) => (snooze_s, if stop_s > 0 { Some(stop_s) } else { None }),
+                                _ => return rouille::Response::text("Invalid alarm settings: the snooze or auto stop time is too long")
+                                            .with_status_code(400)
+                            };
+                            alarm.set_snooze(snooze_length_s, data.alarm_max_snoozes);
+                            alarm.set_auto_stop(auto_stop_s, data.alarm_auto_stop_fade_s);
+                            if let Err(e) = alarm.check() {
+                                return rouille::Response::text(format!("Invalid alarm settings: {}", e)).with_status_code(400);
+                            }
+                            if alarm.is_expired(&clock.now()) {
+                                return rouille::Response::text("The alarm date and time has already passed").with_status_code(400);
+                            }
+                            let (edit, saved) = config.update(|cfg| {
+                                if alarms_revision(cfg) != data.alarm_revision {
+                                    return AlarmEdit::Stale;
+                                }
+                                match cfg.alarms.get_mut(index) {
+                                    Some(a) => {
+                                        // an occurrence that already went off must not go off again
+                                        alarm.inherit_last_fired(a);
+                                        *a = alarm;
+                                        AlarmEdit::Done
+                                    }
+                                    None => AlarmEdit::Missing
+                                }
+                            });
+                            match edit {
+                                AlarmEdit::Done => (),
+                                AlarmEdit::Missing => return rouille::Response::empty_404(),
+                                AlarmEdit::Stale => return stale_response(),
+                            }
 
-                            // We just print what was received on stdout. Of course in a real application
-                            // you probably want to process the data, eg. store it in a database.
-                            println!("Received data: {:?}", data);
+                            debug!("Received data for alarm {}: {:?}", index, data);
+                            commands.lock().unwrap().send(WebCommand::ConfigChanged).unwrap();
 
-                            rouille::Response::redirect_303("/")
+                            saved_response(saved)
                         },
 
                         _ => rouille::Response::empty_404()
@@ -176,14 +529,17 @@ static TEMPLATE: &'static str = r#"
     <head>
         <title>WUMP WebUI</title>
         <script>
-        function alarmModeSelected()\{
-            var elem = document.getElementById("alarm_mode_recurring");
+        function alarmModeSelected(index)\{
+            var elem = document.getElementById("alarm_mode_recurring_" + index);
             var recurring_checked = elem.checked;
 
-            var daymask_elems = document.getElementsByClassName("alarm_daymask");
+            var daymask_elems = document.getElementsByClassName("alarm_daymask_" + index);
             for(i = 0; i < daymask_elems.length; i++) \{
                 daymask_elems[i].disabled = !recurring_checked;
             }
+
+            var dated_checked = document.getElementById("alarm_mode_dated_" + index).checked;
+            document.getElementById("alarm_date_" + index).disabled = !dated_checked;
         }
         </script>
         <style>
@@ -194,31 +550,97 @@ static TEMPLATE: &'static str = r#"
     </head>
     <body>
         <h1>WUMP WebUI</h1>
-        <form action="" method="POST" enctype="multipart/form-data">
-        <h2>Alarm</h2>
-            <p><label><input id="alarm_enabled" type="checkbox" name="alarm_enabled" {alarm_enabled_checked}> Enabled</label></p>
+        {{ for alarm in alarms }}
+        <form action="/alarm/{alarm.alarm_index}" method="POST" enctype="multipart/form-data">
+            <input type="hidden" name="alarm_revision" value="{alarm.alarm_revision}">
+        <h2>Alarm: {alarm.alarm_name}</h2>
+            <p> Name: <input type="text" name="alarm_name" value="{alarm.alarm_name}"></p>
+            <p><label><input type="checkbox" name="alarm_enabled" {alarm.alarm_enabled_checked}> Enabled</label></p>
         <h3>Time</h3>
-        <p> Start time: <input type="time" name="alarm_time" value="{alarm_time}"></p>
+        <p> Start time: <input type="time" name="alarm_time" value="{alarm.alarm_time}"></p>
+        <p> Next: {alarm.alarm_next}</p>
         <p>
-            <label><input id="alarm_mode_onetime" type="radio" name="alarm_mode" onchange="alarmModeSelected();" value="onetime" {alarm_mode_onetime_checked}> Onetime</label>
-            <label><input id="alarm_mode_recurring" type="radio" name="alarm_mode" onchange="alarmModeSelected();" value="recurring" {alarm_mode_recurring_checked}> Recurring</label>
+            <label><input id="alarm_mode_onetime_{alarm.alarm_index}" type="radio" name="alarm_mode" onchange="alarmModeSelected({alarm.alarm_index});" value="onetime" {alarm.alarm_mode_onetime_checked}> Onetime</label>
+            <label><input id="alarm_mode_recurring_{alarm.alarm_index}" type="radio" name="alarm_mode" onchange="alarmModeSelected({alarm.alarm_index});" value="recurring" {alarm.alarm_mode_recurring_checked}> Recurring</label>
+            <label><input id="alarm_mode_dated_{alarm.alarm_index}" type="radio" name="alarm_mode" onchange="alarmModeSelected({alarm.alarm_index});" value="dated" {alarm.alarm_mode_dated_checked}> On date</label>
+            <input id="alarm_date_{alarm.alarm_index}" type="date" name="alarm_date" value="{alarm.alarm_date}" {alarm.alarm_date_disabled}>
         </p>
         <p>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_mon" {alarm_daymask_disabled} {alarm_daymask_mon_checked}> Mon</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_tue" {alarm_daymask_disabled} {alarm_daymask_tue_checked}> Tue</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_wed" {alarm_daymask_disabled} {alarm_daymask_wed_checked}> Wed</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_thu" {alarm_daymask_disabled} {alarm_daymask_thu_checked}> Thu</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_fri" {alarm_daymask_disabled} {alarm_daymask_fri_checked}> Fri</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_sat" {alarm_daymask_disabled} {alarm_daymask_sat_checked}> Sat</label>
-            <label><input class="alarm_daymask" type="checkbox" name="alarm_daymask_sun" {alarm_daymask_disabled} {alarm_daymask_sun_checked}> Sun</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_mon" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_mon_checked}> Mon</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_tue" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_tue_checked}> Tue</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_wed" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_wed_checked}> Wed</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_thu" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_thu_checked}> Thu</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_fri" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_fri_checked}> Fri</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_sat" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_sat_checked}> Sat</label>
+            <label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_daymask_sun" {alarm.alarm_daymask_disabled} {alarm.alarm_daymask_sun_checked}> Sun</label>
         </p>
+        <p><label><input class="alarm_daymask_{alarm.alarm_index}" type="checkbox" name="alarm_skip_next" {alarm.alarm_daymask_disabled} {alarm.alarm_skip_next_checked}> Skip next occurrence</label></p>
+        <h3>Music</h3>
+            <table>
+                <tr><td align="left"> Play:</td> <td align="left">
+                    <select name="alarm_source">
+                        <option value="resume" {alarm.alarm_source_resume_selected}>Current queue</option>
+                        <option value="playlist" {alarm.alarm_source_playlist_selected}>Playlist</option>
+                        <option value="directory" {alarm.alarm_source_directory_selected}>Directory</option>
+                        <option value="stream" {alarm.alarm_source_stream_selected}>Stream URL</option>
+                    </select>
+                </td></tr>
+                <tr><td align="left"> Playlist:</td> <td align="left">
+                    <select name="alarm_source_playlist">
+                        {{ for playlist in alarm.alarm_source_playlists }}<option value="{playlist.name}" {playlist.selected}>{playlist.name}</option>{{ endfor }}
+                    </select>
+                </td></tr>
+                <tr><td align="left"> Directory or URL:</td> <td align="left"><input type="text" name="alarm_source_path" value="{alarm.alarm_source_path}"></td></tr>
+                <tr><td align="left"> Shuffle:</td> <td align="left">
+                    <select name="alarm_shuffle">
+                        <option value="keep" {alarm.alarm_shuffle_keep_selected}>Unchanged</option>
+                        <option value="on" {alarm.alarm_shuffle_on_selected}>On</option>
+                        <option value="off" {alarm.alarm_shuffle_off_selected}>Off</option>
+                    </select>
+                </td></tr>
+                <tr><td align="left"> Repeat:</td> <td align="left">
+                    <select name="alarm_repeat">
+                        <option value="keep" {alarm.alarm_repeat_keep_selected}>Unchanged</option>
+                        <option value="on" {alarm.alarm_repeat_on_selected}>On</option>
+                        <option value="off" {alarm.alarm_repeat_off_selected}>Off</option>
+                    </select>
+                </td></tr>
+            </table>
         <h3>Fade in</h3>
             <table>
-                <tr><td align="left"> Length of fade (seconds):</td> <td align="left"><input type="number" step="1" min="0" name="alarm_fade_length_s" value="{alarm_fade_length_s}"></td></tr>
-                <tr><td align="left"> Start volume (percentage):</td> <td align="left"><input type="number" step="1" min="0" max="100" name="alarm_start_vol" value="{alarm_start_vol}"></td></tr>
-                <tr><td align="left"> End volume (percentage):</td> <td align="left"><input type="number" step="1" min="0" max="100" name="alarm_end_vol" value="{alarm_end_vol}"></td></tr>
+                <tr><td align="left"> Length of fade (seconds):</td> <td align="left"><input type="number" step="1" min="0" name="alarm_fade_length_s" value="{alarm.alarm_fade_length_s}"></td></tr>
+                <tr><td align="left"> Volume curve:</td> <td align="left">
+                    <select name="alarm_fade_curve">
+                        <option value="linear" {alarm.alarm_fade_curve_linear_selected}>Linear</option>
+                        <option value="exponential" {alarm.alarm_fade_curve_exponential_selected}>Exponential</option>
+                        <option value="scurve" {alarm.alarm_fade_curve_scurve_selected}>S-curve</option>
+                        <option value="stepped" {alarm.alarm_fade_curve_stepped_selected}>Stepped</option>
+                    </select>
+                    <input type="number" step="1" min="1" max="100" name="alarm_fade_steps" value="{alarm.alarm_fade_steps}"> steps
+                </td></tr>
+                <tr><td align="left"> Start volume (percentage):</td> <td align="left"><input type="number" step="1" min="0" max="100" name="alarm_start_vol" value="{alarm.alarm_start_vol}"></td></tr>
+                <tr><td align="left"> End volume (percentage):</td> <td align="left"><input type="number" step="1" min="0" max="100" name="alarm_end_vol" value="{alarm.alarm_end_vol}"></td></tr>
             </table>
-            <p><button>Save</button></p>
+        <h3>Snooze</h3>
+            <table>
+                <tr><td align="left"> Length of snooze (minutes):</td> <td align="left"><input type="number" step="1" min="1" name="alarm_snooze_length_min" value="{alarm.alarm_snooze_length_min}"></td></tr>
+                <tr><td align="left"> Maximum number of snoozes:</td> <td align="left"><input type="number" step="1" min="0" name="alarm_max_snoozes" value="{alarm.alarm_max_snoozes}"></td></tr>
+            </table>
+        <h3>Automatic stop</h3>
+            <table>
+                <tr><td align="left"> Stop after (minutes, 0 for never):</td> <td align="left"><input type="number" step="1" min="0" name="alarm_auto_stop_min" value="{alarm.alarm_auto_stop_min}"></td></tr>
+                <tr><td align="left"> Length of fade out (seconds):</td> <td align="left"><input type="number" step="1" min="0" name="alarm_auto_stop_fade_s" value="{alarm.alarm_auto_stop_fade_s}"></td></tr>
+            </table>
+            <p><button>Save</button> <button formaction="/alarm/{alarm.alarm_index}/delete">Delete</button></p>
+        </form>
+        {{ endfor }}
+        <form action="/alarm/new" method="POST">
+            <p><button>Add alarm</button></p>
+        </form>
+        <h2>Sleep timer</h2>
+        <form action="/sleep" method="POST" enctype="multipart/form-data">
+            <p> Stop playing in <input type="number" step="1" min="1" name="sleep_timer_min" value="{sleep_timer_min}"> minutes
+            <button>Start</button> <button formaction="/sleep/cancel">Cancel</button></p>
         </form>
     </body>
 </html>